@@ -0,0 +1,18 @@
+#!/bin/sh
+set -e
+
+# Last release without versioned state, the upgrade integration test migrates its state
+LEGACY_COMMIT=178480049e846bed757ca7dd4137a0d68aab998e
+LEGACY_DIRECTORY=$(mktemp -d)
+trap 'git worktree remove --force ${LEGACY_DIRECTORY}' EXIT
+
+echo ">> Building legacy contract"
+
+git worktree add --detach ${LEGACY_DIRECTORY} ${LEGACY_COMMIT}
+# Dependencies are the same, build them in the versions locked for the current contract
+if [ -f Cargo.lock ]; then
+  cp Cargo.lock ${LEGACY_DIRECTORY}/contract/
+fi
+(cd ${LEGACY_DIRECTORY}/contract && ./build.sh)
+mkdir -p ../out
+cp ${LEGACY_DIRECTORY}/contract/target/wasm32-unknown-unknown/release/stickyhabits.wasm ../out/legacy.wasm
@@ -0,0 +1,204 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, Balance};
+
+use crate::{StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+// What the contract owes in NEAR, token stakes are reported by get_token_balance
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Accounting {
+    // Deposits of unsettled habits and bonds of open disputes
+    pub locked_stakes: U128,
+    // NEP-145 deposits of all accounts, used and available
+    pub storage_deposits: U128,
+    // Developer fees and cancellation penalties not withdrawn by the owner
+    pub dev_fees: U128,
+    // Settled amounts not withdrawn by users, beneficiaries and arbiters, including the owner
+    pub pending_claims: U128,
+    pub liabilities: U128,
+    pub account_balance: U128,
+    // Account balance covers all liabilities
+    pub solvent: bool,
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // Returns breakdown of liabilities in NEAR against the account balance
+    pub fn get_accounting(&self) -> Accounting {
+        let liabilities = self.liabilities();
+        let account_balance = env::account_balance();
+
+        Accounting {
+            locked_stakes: U128(self.balance),
+            storage_deposits: U128(self.storage_deposits),
+            dev_fees: U128(self.dev_fees),
+            pending_claims: U128(self.claimable_total),
+            liabilities: U128(liabilities),
+            account_balance: U128(account_balance),
+            solvent: account_balance >= liabilities,
+        }
+    }
+
+    // Returns account balance left over after all liabilities, fails when they are
+    // not covered
+    #[handle_result]
+    pub fn check_solvency(&self) -> Result<U128, StickyHabitsError> {
+        let liabilities = self.liabilities();
+        let account_balance = env::account_balance();
+
+        if account_balance < liabilities {
+            return Err(StickyHabitsError::Insolvent(liabilities - account_balance));
+        }
+        Ok(U128(account_balance - liabilities))
+    }
+}
+
+impl StickyHabitsContract {
+    fn liabilities(&self) -> Balance {
+        self.balance + self.storage_deposits + self.claimable_total + self.dev_fees
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{
+        accept_role, add_habit, context, register, set_context, DAY, NEAR, OWNER, START,
+    };
+    use near_sdk::json_types::U64;
+    use near_sdk::{testing_env, AccountId};
+    use std::str::FromStr;
+
+    fn set_balance_context(predecessor: &str, amount: Balance, timestamp: u64, balance: Balance) {
+        testing_env!(context(predecessor, amount, timestamp)
+            .account_balance(balance)
+            .build());
+    }
+
+    #[test]
+    fn accounts_large_deposits() {
+        let mut contract = StickyHabitsContract::default();
+        let deposit = 10_000_000 * NEAR;
+
+        register(&mut contract, "roman");
+        // Attached deposit is added to the account balance
+        set_balance_context("roman", deposit, START, NEAR);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+
+        // Balance above u64::MAX is not truncated
+        env::state_write(&contract);
+        assert_eq!(contract.get_balance(), Ok(U128(deposit)));
+        let accounting = contract.get_accounting();
+        assert_eq!(accounting.locked_stakes, U128(deposit));
+        assert_eq!(accounting.storage_deposits, U128(NEAR));
+        assert_eq!(accounting.liabilities, U128(deposit + NEAR));
+        assert!(accounting.solvent);
+        assert_eq!(contract.check_solvency(), Ok(U128(0)));
+
+        set_balance_context("roman", 0, START, deposit);
+        assert!(!contract.get_accounting().solvent);
+        assert_eq!(
+            contract.check_solvency(),
+            Err(StickyHabitsError::Insolvent(NEAR))
+        );
+    }
+
+    #[test]
+    fn accounts_many_deposits() {
+        let mut contract = StickyHabitsContract::default();
+        let users = ["roman", "adam", "maria", "eva"];
+        let mut total = 0;
+
+        for user in users {
+            register(&mut contract, user);
+            for i in 1..=25 {
+                let description = format!("Do {} push-ups everyday", i);
+                add_habit(&mut contract, user, &description, "josef", i * 1_000 * NEAR);
+                total += i * 1_000 * NEAR;
+            }
+        }
+        let accounting = contract.get_accounting();
+        assert_eq!(accounting.locked_stakes, U128(total));
+        assert_eq!(accounting.storage_deposits, U128(4 * NEAR));
+
+        // Settled deposits move to claims, withdrawals leave the books
+        let roman = AccountId::from_str("roman").unwrap();
+        set_balance_context("roman", 0, START + 4 * DAY, 1_000_000 * NEAR);
+        let ids: Vec<String> = contract
+            .get_habits_user(roman.clone(), None, Some(25))
+            .items
+            .iter()
+            .map(|h| h.id.clone())
+            .collect();
+        for id in ids {
+            contract.unlock_deposit_by_id(id).unwrap();
+        }
+        let refunded = 325 * 1_000 * NEAR;
+        let accounting = contract.get_accounting();
+        assert_eq!(accounting.locked_stakes, U128(total - refunded));
+        assert_eq!(accounting.pending_claims, U128(refunded));
+        assert_eq!(accounting.dev_fees, U128(0));
+
+        set_balance_context("roman", 1, START + 4 * DAY, 1_000_000 * NEAR);
+        contract.withdraw(None, None, None).unwrap();
+        contract.storage_withdraw(None).unwrap();
+        let accounting = contract.get_accounting();
+        assert_eq!(accounting.pending_claims, U128(0));
+        assert!(accounting.storage_deposits.0 < 4 * NEAR);
+        assert_eq!(
+            accounting.liabilities,
+            U128(total - refunded + accounting.storage_deposits.0)
+        );
+    }
+
+    #[test]
+    fn separates_dev_fees() {
+        set_context(OWNER, 0, START);
+        let mut contract =
+            StickyHabitsContract::init(OWNER.parse().unwrap(), U64(500), U64(DAY), U64(DAY));
+        let joe = AccountId::from_str(OWNER).unwrap();
+
+        // Owner's own habit is refunded, roman's habit is forfeited
+        let mut habits = vec![];
+        for (user, deposit) in [(OWNER, 10 * NEAR), ("roman", 20 * NEAR)] {
+            register(&mut contract, user);
+            let description = "Do 15 push-ups everyday";
+            habits.push(add_habit(
+                &mut contract,
+                user,
+                description,
+                "josef",
+                deposit,
+            ));
+        }
+        let (joe_habit, roman_habit) = (habits[0].clone(), habits[1].clone());
+        accept_role(&mut contract, "roman", 0, START);
+
+        set_context(OWNER, 0, START + 4 * DAY);
+        contract.unlock_deposit_by_id(joe_habit).unwrap();
+        set_context("roman", 0, START + 4 * DAY);
+        contract.unlock_deposit_by_id(roman_habit).unwrap();
+
+        // Refund of the owner is a claim, not a fee
+        let accounting = contract.get_accounting();
+        assert_eq!(accounting.dev_fees, U128(NEAR));
+        assert_eq!(accounting.pending_claims, U128(29 * NEAR));
+        assert_eq!(contract.get_claimable(joe.clone(), None), U128(11 * NEAR));
+
+        // Owner's withdrawal takes the fees first
+        set_context(OWNER, 1, START + 4 * DAY);
+        contract.withdraw(Some(U128(2 * NEAR)), None, None).unwrap();
+        let accounting = contract.get_accounting();
+        assert_eq!(accounting.dev_fees, U128(0));
+        assert_eq!(accounting.pending_claims, U128(28 * NEAR));
+        assert_eq!(contract.get_claimable(joe, None), U128(9 * NEAR));
+    }
+}
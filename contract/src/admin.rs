@@ -0,0 +1,252 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::settlement::BPS_DENOMINATOR;
+use crate::{StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+// Current contract settings, new habits are created under these terms
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Config {
+    pub owner: AccountId,
+    pub proposed_owner: Option<AccountId>,
+    pub dev_fee_bps: U64,
+    pub habit_acquisition_period: U64,
+    pub approval_grace_period: U64,
+    pub cancellation_period: U64,
+    pub cancellation_penalty_bps: U64,
+    pub acceptance_period: U64,
+    pub dispute_period: U64,
+    pub dispute_bond: U128,
+    pub ruling_period: U64,
+    pub max_description_length: u32,
+    pub max_evidence_length: u32,
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // Sets developer fee in basis points for habits created from now on
+    #[payable]
+    #[handle_result]
+    pub fn set_dev_fee_bps(&mut self, dev_fee_bps: U64) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        if u64::from(dev_fee_bps) > BPS_DENOMINATOR {
+            return Err(StickyHabitsError::InvalidDevFee(u64::from(dev_fee_bps)));
+        }
+        self.dev_fee_bps = u64::from(dev_fee_bps);
+        Ok(())
+    }
+
+    // Sets acquisition period in nanoseconds for habits created from now on
+    #[payable]
+    #[handle_result]
+    pub fn set_habit_acquisition_period(
+        &mut self,
+        habit_acquisition_period: U64,
+    ) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.habit_acquisition_period = u64::from(habit_acquisition_period);
+        Ok(())
+    }
+
+    // Sets approval grace period in nanoseconds for habits created from now on
+    #[payable]
+    #[handle_result]
+    pub fn set_approval_grace_period(
+        &mut self,
+        approval_grace_period: U64,
+    ) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.approval_grace_period = u64::from(approval_grace_period);
+        Ok(())
+    }
+
+    // Sets cool-off period in nanoseconds after creation, when habits created from now on
+    // can be cancelled
+    #[payable]
+    #[handle_result]
+    pub fn set_cancellation_period(
+        &mut self,
+        cancellation_period: U64,
+    ) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.cancellation_period = u64::from(cancellation_period);
+        Ok(())
+    }
+
+    // Sets penalty in basis points kept from the refund of habits created from now on
+    // and cancelled
+    #[payable]
+    #[handle_result]
+    pub fn set_cancellation_penalty_bps(
+        &mut self,
+        cancellation_penalty_bps: U64,
+    ) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        if u64::from(cancellation_penalty_bps) > BPS_DENOMINATOR {
+            return Err(StickyHabitsError::InvalidCancellationPenalty(u64::from(
+                cancellation_penalty_bps,
+            )));
+        }
+        self.cancellation_penalty_bps = u64::from(cancellation_penalty_bps);
+        Ok(())
+    }
+
+    // Sets period in nanoseconds beneficiary has to accept the role for habits
+    // created from now on
+    #[payable]
+    #[handle_result]
+    pub fn set_acceptance_period(
+        &mut self,
+        acceptance_period: U64,
+    ) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.acceptance_period = u64::from(acceptance_period);
+        Ok(())
+    }
+
+    // Sets period in nanoseconds after rejection of habits created from now on, when user
+    // can contest it before the deposit is forfeited
+    #[payable]
+    #[handle_result]
+    pub fn set_dispute_period(&mut self, dispute_period: U64) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.dispute_period = u64::from(dispute_period);
+        Ok(())
+    }
+
+    // Sets bond in yoctoNEAR to be attached when opening a dispute
+    #[payable]
+    #[handle_result]
+    pub fn set_dispute_bond(&mut self, dispute_bond: U128) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.dispute_bond = u128::from(dispute_bond);
+        Ok(())
+    }
+
+    // Sets period in nanoseconds for arbiters to rule on disputes opened from now on
+    #[payable]
+    #[handle_result]
+    pub fn set_ruling_period(&mut self, ruling_period: U64) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.ruling_period = u64::from(ruling_period);
+        Ok(())
+    }
+
+    // Sets upper bound of habit description in bytes
+    #[payable]
+    #[handle_result]
+    pub fn set_max_description_length(
+        &mut self,
+        max_description_length: u32,
+    ) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.max_description_length = max_description_length;
+        Ok(())
+    }
+
+    // Sets upper bound of evidence link in bytes
+    #[payable]
+    #[handle_result]
+    pub fn set_max_evidence_length(
+        &mut self,
+        max_evidence_length: u32,
+    ) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.max_evidence_length = max_evidence_length;
+        Ok(())
+    }
+
+    // Allows the account to rule on disputes
+    #[payable]
+    #[handle_result]
+    pub fn add_arbiter(&mut self, arbiter: AccountId) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.arbiters.insert(&arbiter);
+        Ok(())
+    }
+
+    #[payable]
+    #[handle_result]
+    pub fn remove_arbiter(&mut self, arbiter: AccountId) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.arbiters.remove(&arbiter);
+        Ok(())
+    }
+
+    // Accepts the NEP-141 token as stake of new habits
+    #[payable]
+    #[handle_result]
+    pub fn add_whitelisted_token(&mut self, token: AccountId) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.whitelisted_tokens.insert(&token);
+        Ok(())
+    }
+
+    // Habits already staked with the token are settled in it
+    #[payable]
+    #[handle_result]
+    pub fn remove_whitelisted_token(&mut self, token: AccountId) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.whitelisted_tokens.remove(&token);
+        Ok(())
+    }
+
+    // First step of ownership transfer, proposed account has to accept it
+    #[payable]
+    #[handle_result]
+    pub fn propose_owner(&mut self, new_owner: AccountId) -> Result<(), StickyHabitsError> {
+        self.check_owner()?;
+        self.proposed_owner = Some(new_owner);
+        Ok(())
+    }
+
+    // Second step of ownership transfer, called by the proposed account.
+    // Requires exactly 1 yoctoNEAR attached.
+    #[payable]
+    #[handle_result]
+    pub fn accept_ownership(&mut self) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+        if self.proposed_owner.as_ref() != Some(&account) {
+            return Err(StickyHabitsError::NotProposedOwner);
+        }
+        if env::attached_deposit() != 1 {
+            return Err(StickyHabitsError::OneYoctoRequired);
+        }
+        self.owner = account;
+        self.proposed_owner = None;
+        Ok(())
+    }
+
+    // Returns current contract settings
+    pub fn get_config(&self) -> Config {
+        Config {
+            owner: self.owner.clone(),
+            proposed_owner: self.proposed_owner.clone(),
+            dev_fee_bps: U64(self.dev_fee_bps),
+            habit_acquisition_period: U64(self.habit_acquisition_period),
+            approval_grace_period: U64(self.approval_grace_period),
+            cancellation_period: U64(self.cancellation_period),
+            cancellation_penalty_bps: U64(self.cancellation_penalty_bps),
+            acceptance_period: U64(self.acceptance_period),
+            dispute_period: U64(self.dispute_period),
+            dispute_bond: U128(self.dispute_bond),
+            ruling_period: U64(self.ruling_period),
+            max_description_length: self.max_description_length,
+            max_evidence_length: self.max_evidence_length,
+        }
+    }
+
+    // Owner calls have to attach exactly 1 yoctoNEAR, so that they are signed
+    // with a full access key
+    pub(crate) fn check_owner(&self) -> Result<(), StickyHabitsError> {
+        if env::predecessor_account_id() != self.owner {
+            return Err(StickyHabitsError::NotOwner);
+        }
+        if env::attached_deposit() != 1 {
+            return Err(StickyHabitsError::OneYoctoRequired);
+        }
+        Ok(())
+    }
+}
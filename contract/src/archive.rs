@@ -0,0 +1,267 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::Vector;
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::events::{HabitArchivedData, HabitEvent};
+use crate::pagination::{
+    paginate, vector_page, Page, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT, SCANNED_PER_ITEM,
+};
+use crate::{Habit, HabitStatus, StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+// Compact record of a settled habit moved out of the user's active habits
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HabitSummary {
+    pub id: String,
+    pub description: String,
+    pub deadline: U64,
+    pub created_at: U64,
+    pub beneficiaries: Vec<AccountId>,
+    pub status: HabitStatus,
+}
+
+impl From<Habit> for HabitSummary {
+    fn from(habit: Habit) -> Self {
+        Self {
+            id: habit.id,
+            description: habit.description,
+            deadline: habit.deadline,
+            created_at: habit.created_at,
+            beneficiaries: habit.beneficiaries,
+            status: habit.status,
+        }
+    }
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // User moves up to limit_to settled habits out of the active habits, together with their
+    // evidence history. Only the last limit_to * SCANNED_PER_ITEM habits are looked at, most
+    // recent first. Summaries are kept unless keep_summaries is false, released storage
+    // goes back to the user's storage balance. Remaining habits keep their ids and creation
+    // order, their index may change. Returns number of archived habits.
+    #[handle_result]
+    pub fn archive_settled_habits(
+        &mut self,
+        keep_summaries: Option<bool>,
+        limit_to: Option<u16>,
+    ) -> Result<u16, StickyHabitsError> {
+        let user: AccountId = env::predecessor_account_id();
+        let keep_summaries = keep_summaries.unwrap_or(true);
+        let limit = usize::from(limit_to.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT));
+        let initial_storage = self.accounted_storage_usage();
+
+        let mut existing_habits = match self.habits.get(&user) {
+            Some(v) => v,
+            None => return Ok(0),
+        };
+        let mut summaries = match self.archived_habits.get(&user) {
+            Some(v) => v,
+            None => Vector::new(
+                ("vector-a-id-".to_string() + user.as_str())
+                    .as_bytes()
+                    .to_vec(),
+            ),
+        };
+
+        // Only the last habits are read, so that the work does not grow with all user's habits.
+        // Most recent settled habits are archived first, habits after them are moved up in place,
+        // so that they stay in creation order.
+        let len = existing_habits.len();
+        let window_start = len.saturating_sub((limit * SCANNED_PER_ITEM) as u64);
+        let mut archived: Vec<Habit> = vec![];
+        let mut kept: Vec<Habit> = vec![];
+        let mut moved = 0;
+        for index in (window_start..len).rev() {
+            if archived.len() >= limit {
+                break;
+            }
+            let habit = existing_habits.get(index).unwrap();
+            if habit.status.is_settled() {
+                archived.push(habit);
+                moved = kept.len();
+            } else {
+                kept.push(habit);
+            }
+        }
+        if archived.is_empty() {
+            return Ok(0);
+        }
+
+        kept.truncate(moved);
+        let from = len - (archived.len() + kept.len()) as u64;
+        for (index, habit) in (from..).zip(kept.iter().rev()) {
+            existing_habits.replace(index, habit);
+            self.habit_index.insert(&habit.id, &(user.clone(), index));
+        }
+        for _ in 0..archived.len() {
+            existing_habits.pop();
+        }
+
+        let mut archived_ids: Vec<String> = vec![];
+        for habit in archived.into_iter().rev() {
+            self.habit_index.remove(&habit.id);
+            self.remove_beneficiary_habits(&habit);
+            self.remove_feed_entries(&habit);
+            if let Some(mut history) = self.evidence.remove(&habit.id) {
+                history.clear();
+            }
+
+            archived_ids.push(habit.id.clone());
+            if keep_summaries {
+                summaries.push(&HabitSummary::from(habit));
+            }
+        }
+
+        self.habits.insert(&user, &existing_habits);
+        if keep_summaries {
+            self.archived_habits.insert(&user, &summaries);
+        }
+        self.track_storage(&user, initial_storage, true)?;
+
+        HabitEvent::HabitArchived(
+            archived_ids
+                .iter()
+                .map(|id| HabitArchivedData {
+                    user: user.clone(),
+                    habit_id: id.clone(),
+                    summary_kept: keep_summaries,
+                })
+                .collect(),
+        )
+        .emit();
+
+        Ok(archived_ids.len() as u16)
+    }
+
+    // Returns page of summaries of user's archived habits in order of archiving
+    pub fn get_archived_habits(
+        &self,
+        user: AccountId,
+        cursor: Option<U64>,
+        limit_to: Option<u16>,
+    ) -> Page<HabitSummary> {
+        match self.archived_habits.get(&user) {
+            Some(summaries) => vector_page(&summaries, cursor, limit_to),
+            None => paginate(std::iter::empty(), limit_to),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{add_habit, register, set_context, NEAR, START};
+    use std::str::FromStr;
+
+    // Auxiliary fn: user adds habits with given descriptions, josef is beneficiary
+    fn add_habits(contract: &mut StickyHabitsContract, user: &str, descriptions: &[&str]) {
+        register(contract, user);
+        for description in descriptions {
+            add_habit(contract, user, description, "josef", 10 * NEAR);
+        }
+    }
+
+    #[test]
+    fn archives_settled_habits() {
+        let mut contract = StickyHabitsContract::default();
+        let roman = AccountId::from_str("roman").unwrap();
+        add_habits(
+            &mut contract,
+            "roman",
+            &[
+                "Do 15 push-ups everyday",
+                "Eat vegetarian food once a day",
+                "Read 10 pages every evening",
+            ],
+        );
+        let ids: Vec<String> = contract
+            .get_habits_user(roman.clone(), None, Some(3))
+            .items
+            .iter()
+            .map(|h| h.id.clone())
+            .collect();
+
+        // First habit is refunded after decline, second cancelled, third stays active
+        set_context("roman", 0, START);
+        contract
+            .update_evidence(
+                roman.clone(),
+                0,
+                "https://www.icloud.com/myfile.mov".to_string(),
+                None,
+            )
+            .unwrap();
+        contract.cancel_habit(ids[1].clone()).unwrap();
+        set_context("josef", 0, START);
+        contract.decline_beneficiary_role(ids[0].clone()).unwrap();
+        let available = contract
+            .storage_balance_of(roman.clone())
+            .unwrap()
+            .available;
+
+        // Most recent settled habit goes first
+        set_context("roman", 0, START);
+        assert_eq!(contract.archive_settled_habits(None, Some(1)), Ok(1));
+        assert!(contract.get_habit(ids[1].clone()).is_none());
+        assert_eq!(contract.archive_settled_habits(None, None), Ok(1));
+        assert_eq!(contract.archive_settled_habits(None, None), Ok(0));
+        assert!(
+            contract
+                .storage_balance_of(roman.clone())
+                .unwrap()
+                .available
+                .0
+                > available.0
+        );
+
+        // Active habit is remapped to its new index
+        let habits = contract.get_habits_user(roman.clone(), None, Some(3)).items;
+        assert_eq!(habits.len(), 1);
+        assert_eq!(habits[0].id, ids[2]);
+        assert_eq!(contract.get_habit(ids[2].clone()).unwrap().id, ids[2]);
+        contract
+            .update_evidence(
+                roman.clone(),
+                0,
+                "https://www.icloud.com/otherfile.mov".to_string(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            contract.get_habit(ids[2].clone()).unwrap().evidence,
+            "https://www.icloud.com/otherfile.mov"
+        );
+
+        assert!(contract.get_habit(ids[0].clone()).is_none());
+        assert!(contract
+            .get_evidence(ids[0].clone(), None, None)
+            .items
+            .is_empty());
+        let summaries = contract.get_archived_habits(roman, None, None).items;
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].id, ids[1]);
+        assert_eq!(summaries[0].status, HabitStatus::Cancelled);
+        assert_eq!(summaries[1].status, HabitStatus::Refunded);
+
+        // Without summaries the habits are deleted
+        add_habits(&mut contract, "adam", &["Do 15 push-ups everyday"]);
+        let adam = AccountId::from_str("adam").unwrap();
+        let id = contract.get_habits_user(adam.clone(), None, None).items[0]
+            .id
+            .clone();
+        set_context("adam", 0, START);
+        contract.cancel_habit(id).unwrap();
+        assert_eq!(contract.archive_settled_habits(Some(false), None), Ok(1));
+        assert!(contract
+            .get_habits_user(adam.clone(), None, None)
+            .items
+            .is_empty());
+        assert!(contract
+            .get_archived_habits(adam, None, None)
+            .items
+            .is_empty());
+    }
+}
@@ -0,0 +1,277 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId, Balance, Gas, PromiseResult};
+
+use crate::events::{HabitEvent, WithdrawalData};
+use crate::{StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+const ON_WITHDRAW_GAS: Gas = Gas(10_000_000_000_000);
+
+// Amount the account can withdraw, storage of the entry is charged to the payer until
+// it is withdrawn
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Claim {
+    pub amount: Balance,
+    pub payer: AccountId,
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // Pays out amount credited to the caller by settlements, all of it by default, to the
+    // receiver or the caller. Token is None for NEAR. Failed transfer is credited back to
+    // the caller, so a failed settlement payout is retried by calling withdraw again.
+    // Requires exactly 1 yoctoNEAR attached.
+    #[payable]
+    #[handle_result]
+    pub fn withdraw(
+        &mut self,
+        amount: Option<U128>,
+        token: Option<AccountId>,
+        receiver: Option<AccountId>,
+    ) -> Result<U128, StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+        let receiver = receiver.unwrap_or_else(|| account.clone());
+
+        if env::attached_deposit() != 1 {
+            return Err(StickyHabitsError::OneYoctoRequired);
+        }
+        let claimable = self.withdrawable_of(&account, &token);
+        if claimable == 0 {
+            return Err(StickyHabitsError::NothingToWithdraw);
+        }
+        let amount = amount.map_or(claimable, u128::from);
+        if amount > claimable {
+            return Err(StickyHabitsError::InsufficientClaimable(amount - claimable));
+        }
+
+        // Owner's withdrawal is paid from developer fees first
+        let from_fees = if token.is_none() && account == self.owner {
+            amount.min(self.dev_fees)
+        } else {
+            0
+        };
+        self.dev_fees -= from_fees;
+        self.debit(&account, &token, amount - from_fees);
+        self.transfer_stake(&token, receiver.clone(), amount).then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(ON_WITHDRAW_GAS)
+                .on_withdraw(
+                    account.clone(),
+                    receiver.clone(),
+                    token.clone(),
+                    U128(amount),
+                ),
+        );
+
+        HabitEvent::Withdrawn(vec![WithdrawalData {
+            account,
+            receiver,
+            token,
+            amount: U128(amount),
+        }])
+        .emit();
+
+        Ok(U128(amount))
+    }
+
+    // Result of a withdrawal, failed amount is credited back to the account
+    #[private]
+    pub fn on_withdraw(
+        &mut self,
+        account: AccountId,
+        receiver: AccountId,
+        token: Option<AccountId>,
+        amount: U128,
+    ) {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+
+        self.credit(&account, &token, amount.0, &account);
+        HabitEvent::WithdrawalFailed(vec![WithdrawalData {
+            account,
+            receiver,
+            token,
+            amount,
+        }])
+        .emit();
+    }
+
+    // Returns amount the account can withdraw in NEAR or in the token
+    pub fn get_claimable(&self, account: AccountId, token: Option<AccountId>) -> U128 {
+        U128(self.withdrawable_of(&account, &token))
+    }
+}
+
+impl StickyHabitsContract {
+    pub(crate) fn claimable_of(&self, account: &AccountId, token: &Option<AccountId>) -> Balance {
+        self.claimable
+            .get(&(account.clone(), token.clone()))
+            .map_or(0, |claim| claim.amount)
+    }
+
+    // Claimable amount together with developer fees for the owner
+    fn withdrawable_of(&self, account: &AccountId, token: &Option<AccountId>) -> Balance {
+        let claimable = self.claimable_of(account, token);
+        if token.is_none() && account == &self.owner {
+            claimable + self.dev_fees
+        } else {
+            claimable
+        }
+    }
+
+    // Developer's part of a settled deposit, token fees are credited to the owner's claims
+    pub(crate) fn credit_dev_fee(
+        &mut self,
+        token: &Option<AccountId>,
+        amount: Balance,
+        payer: &AccountId,
+    ) {
+        match token {
+            Some(_) => self.credit(&self.owner.clone(), token, amount, payer),
+            None => self.dev_fees += amount,
+        }
+    }
+
+    // Adds part of a settled deposit to what the account can withdraw. New entry is charged
+    // to the payer's storage, so that settlements cannot grow the ledger at contract's cost.
+    pub(crate) fn credit(
+        &mut self,
+        account: &AccountId,
+        token: &Option<AccountId>,
+        amount: Balance,
+        payer: &AccountId,
+    ) {
+        if amount == 0 {
+            return;
+        }
+        let key = (account.clone(), token.clone());
+        if token.is_none() {
+            self.claimable_total += amount;
+        }
+        if let Some(mut claim) = self.claimable.get(&key) {
+            claim.amount += amount;
+            self.claimable.insert(&key, &claim);
+            return;
+        }
+
+        let initial_storage = env::storage_usage();
+        self.claimable.insert(
+            &key,
+            &Claim {
+                amount,
+                payer: payer.clone(),
+            },
+        );
+        let bytes = env::storage_usage() - initial_storage;
+        self.claims_storage += bytes;
+        self.charge_storage(payer, bytes);
+    }
+
+    // Entry is removed once everything is withdrawn, its storage goes back to the payer
+    fn debit(&mut self, account: &AccountId, token: &Option<AccountId>, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+        let key = (account.clone(), token.clone());
+        let mut claim = self.claimable.get(&key).unwrap();
+        if token.is_none() {
+            self.claimable_total -= amount;
+        }
+        claim.amount -= amount;
+        if claim.amount > 0 {
+            self.claimable.insert(&key, &claim);
+            return;
+        }
+
+        let initial_storage = env::storage_usage();
+        self.claimable.remove(&key);
+        let bytes = initial_storage - env::storage_usage();
+        self.claims_storage -= bytes;
+        self.release_storage(&claim.payer, bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{add_habit, context, register, set_context, DAY, NEAR, START};
+    use crate::HabitStatus;
+    use near_sdk::test_utils::get_created_receipts;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+    use std::str::FromStr;
+
+    // Contract calls itself back with the result of the transfer
+    fn set_callback_context(result: PromiseResult) {
+        testing_env!(
+            context(env::current_account_id().as_str(), 0, START + 4 * DAY).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    #[test]
+    fn withdraws_claimable() {
+        let mut contract = StickyHabitsContract::default();
+        let roman = AccountId::from_str("roman").unwrap();
+
+        register(&mut contract, "roman");
+        let id = add_habit(
+            &mut contract,
+            "roman",
+            "Do 15 push-ups everyday",
+            "josef",
+            20 * NEAR,
+        );
+
+        // Not accepted habit is credited to roman, nothing is transferred
+        set_context("roman", 0, START + 4 * DAY);
+        contract.unlock_deposit_by_id(id.clone()).unwrap();
+        assert_eq!(
+            contract.get_habit(id.clone()).unwrap().status,
+            HabitStatus::Refunded
+        );
+        assert!(get_created_receipts().is_empty());
+        assert_eq!(contract.balance, 0);
+        assert_eq!(contract.get_claimable(roman.clone(), None), U128(20 * NEAR));
+
+        assert_eq!(
+            contract.withdraw(None, None, None),
+            Err(StickyHabitsError::OneYoctoRequired)
+        );
+        set_context("roman", 1, START + 4 * DAY);
+        assert_eq!(
+            contract.withdraw(Some(U128(21 * NEAR)), None, None),
+            Err(StickyHabitsError::InsufficientClaimable(NEAR))
+        );
+        // Part of it goes to another account
+        let adam = AccountId::from_str("adam").unwrap();
+        assert_eq!(
+            contract.withdraw(Some(U128(5 * NEAR)), None, Some(adam.clone())),
+            Ok(U128(5 * NEAR))
+        );
+        assert_eq!(get_created_receipts()[0].receiver_id, adam);
+        assert_eq!(contract.withdraw(None, None, None), Ok(U128(15 * NEAR)));
+        assert_eq!(contract.get_claimable(roman.clone(), None), U128(0));
+        assert_eq!(
+            contract.withdraw(None, None, None),
+            Err(StickyHabitsError::NothingToWithdraw)
+        );
+
+        // Roman's account was deleted meanwhile, amount can be withdrawn again
+        set_callback_context(PromiseResult::Failed);
+        contract.on_withdraw(roman.clone(), roman.clone(), None, U128(15 * NEAR));
+        assert_eq!(contract.get_claimable(roman.clone(), None), U128(15 * NEAR));
+        assert_eq!(contract.get_claimable(adam.clone(), None), U128(0));
+        set_callback_context(PromiseResult::Successful(vec![]));
+        contract.on_withdraw(roman.clone(), adam, None, U128(5 * NEAR));
+        assert_eq!(contract.get_claimable(roman.clone(), None), U128(15 * NEAR));
+
+        // Retried withdrawal pays out the failed amount again
+        set_context("roman", 1, START + 5 * DAY);
+        assert_eq!(contract.withdraw(None, None, None), Ok(U128(15 * NEAR)));
+        assert_eq!(contract.get_claimable(roman, None), U128(0));
+    }
+}
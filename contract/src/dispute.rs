@@ -0,0 +1,531 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance};
+
+use crate::events::{DisputeExpiredData, DisputeOpenedData, DisputeResolvedData, HabitEvent};
+use crate::pagination::{vector_page, Page};
+use crate::{HabitStatus, StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+// Contested habit waiting for or settled by arbiter's ruling. Without ruling until the
+// deadline the habit goes back to its previous status.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Dispute {
+    pub habit_id: String,
+    pub user: AccountId,
+    pub opened_by: AccountId,
+    pub bond: U128,
+    pub opened_at: U64,
+    pub ruling: Option<Ruling>,
+    // Status of the habit before the dispute was opened
+    pub previous_status: HabitStatus,
+    pub ruling_deadline: U64,
+    pub expired: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Ruling {
+    pub arbiter: AccountId,
+    // true overrides beneficiary's decision in favour of the user
+    pub approved: bool,
+    pub resolved_at: U64,
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // User or beneficiary contests the habit before it can be unlocked, attached deposit
+    // has to match the dispute bond. Bond is returned if the ruling goes the opener's way
+    // or arbiters do not rule in time, otherwise it is paid to the arbiter.
+    #[payable]
+    #[handle_result]
+    pub fn open_dispute(&mut self, id: String) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+        let bond: Balance = env::attached_deposit();
+        let current_time = env::block_timestamp();
+
+        let initial_storage = self.accounted_storage_usage();
+
+        let (user, index) = self.habit_location(&id)?;
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        if !habit.is_beneficiary(&account) && account != user {
+            return Err(StickyHabitsError::NotUserOrBeneficiary);
+        }
+        if self.arbiters.is_empty() {
+            return Err(StickyHabitsError::NoArbiters);
+        }
+        if bond != self.dispute_bond {
+            return Err(StickyHabitsError::InvalidDisputeBond(self.dispute_bond));
+        }
+        if !habit.beneficiary_accepted {
+            return Err(StickyHabitsError::BeneficiaryNotAccepted);
+        }
+        if habit.status.is_settled() {
+            return Err(StickyHabitsError::AlreadySettled);
+        }
+        // Habit is disputed at most once, also when the dispute expired
+        if habit.status == HabitStatus::Disputed || self.disputes.get(&id).is_some() {
+            return Err(StickyHabitsError::DisputeAlreadyOpen);
+        }
+        if self.unlockable_at(&habit) <= current_time {
+            return Err(StickyHabitsError::DisputePeriodOver);
+        }
+
+        let previous_status = habit.status;
+        habit.status = HabitStatus::Disputed;
+        let _updated = existing_habits.replace(index, &habit);
+        self.balance += bond;
+        self.disputes.insert(
+            &id,
+            &Dispute {
+                habit_id: id.clone(),
+                user: user.clone(),
+                opened_by: account.clone(),
+                bond: U128(bond),
+                opened_at: U64(current_time),
+                ruling: None,
+                previous_status,
+                ruling_deadline: U64(current_time + self.ruling_period),
+                expired: false,
+            },
+        );
+
+        // Dispute record is paid by whoever opened it
+        self.track_storage(&account, initial_storage, false)?;
+
+        HabitEvent::DisputeOpened(vec![DisputeOpenedData {
+            user,
+            habit_id: id,
+            opened_by: account,
+            bond: U128(bond),
+        }])
+        .emit();
+
+        Ok(())
+    }
+
+    // Arbiter rules on the dispute, approved habit is refunded to the user,
+    // otherwise the deposit is forfeited
+    #[handle_result]
+    pub fn resolve_dispute(&mut self, id: String, approved: bool) -> Result<(), StickyHabitsError> {
+        let arbiter: AccountId = env::predecessor_account_id();
+
+        if !self.arbiters.contains(&arbiter) {
+            return Err(StickyHabitsError::NotArbiter);
+        }
+        let mut dispute = self
+            .disputes
+            .get(&id)
+            .ok_or_else(|| StickyHabitsError::DisputeNotFound(id.clone()))?;
+        if dispute.ruling.is_some() || dispute.expired {
+            return Err(StickyHabitsError::DisputeAlreadyResolved);
+        }
+        if u64::from(dispute.ruling_deadline) < env::block_timestamp() {
+            return Err(StickyHabitsError::RulingPeriodOver);
+        }
+
+        let (user, index) = self.habit_location(&id)?;
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        habit.status = if approved {
+            HabitStatus::Approved
+        } else {
+            HabitStatus::Rejected
+        };
+
+        // Opener wins when the ruling is what the opener argued for
+        let opener_won = (dispute.opened_by == user) == approved;
+        let bond_to = if opener_won {
+            dispute.opened_by.clone()
+        } else {
+            arbiter.clone()
+        };
+        self.credit(&bond_to, &None, dispute.bond.0, &dispute.opened_by);
+        self.balance -= dispute.bond.0;
+
+        let initial_storage = self.accounted_storage_usage();
+        dispute.ruling = Some(Ruling {
+            arbiter: arbiter.clone(),
+            approved,
+            resolved_at: U64(env::block_timestamp()),
+        });
+        self.disputes.insert(&id, &dispute);
+        self.track_storage(&dispute.opened_by, initial_storage, false)?;
+
+        HabitEvent::DisputeResolved(vec![DisputeResolvedData {
+            user: user.clone(),
+            habit_id: id,
+            arbiter,
+            approved,
+            bond_to,
+        }])
+        .emit();
+
+        let initial_storage = self.accounted_storage_usage();
+        self.settle_action(index, user.clone(), &mut existing_habits, &mut habit);
+        self.track_storage(&user, initial_storage, false)
+    }
+
+    // Anyone closes the dispute arbiters did not rule on until its deadline. Bond is returned
+    // to the opener and the habit goes back to its previous status. Its voting windows are
+    // extended by the time it was disputed, so they do not run out while waiting for arbiters.
+    #[handle_result]
+    pub fn expire_dispute(&mut self, id: String) -> Result<(), StickyHabitsError> {
+        let current_time = env::block_timestamp();
+
+        let mut dispute = self
+            .disputes
+            .get(&id)
+            .ok_or_else(|| StickyHabitsError::DisputeNotFound(id.clone()))?;
+        if dispute.ruling.is_some() || dispute.expired {
+            return Err(StickyHabitsError::DisputeAlreadyResolved);
+        }
+        if u64::from(dispute.ruling_deadline) >= current_time {
+            return Err(StickyHabitsError::RulingPeriodNotOver);
+        }
+
+        let initial_storage = self.accounted_storage_usage();
+        let (user, index) = self.habit_location(&id)?;
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        habit.status = dispute.previous_status;
+        habit.disputed_for = U64(current_time - u64::from(dispute.opened_at));
+        let _updated = existing_habits.replace(index, &habit);
+
+        self.credit(
+            &dispute.opened_by,
+            &None,
+            dispute.bond.0,
+            &dispute.opened_by,
+        );
+        self.balance -= dispute.bond.0;
+        dispute.expired = true;
+        self.disputes.insert(&id, &dispute);
+
+        HabitEvent::DisputeExpired(vec![DisputeExpiredData {
+            user: user.clone(),
+            habit_id: id,
+            opened_by: dispute.opened_by,
+            bond: dispute.bond,
+        }])
+        .emit();
+
+        self.track_storage(&user, initial_storage, false)
+    }
+
+    // Returns the dispute over habit with given id, if any
+    pub fn get_dispute(&self, id: String) -> Option<Dispute> {
+        self.disputes.get(&id)
+    }
+
+    // Returns page of disputes in order of opening, cursor is the position of the last one
+    pub fn get_disputes(&self, cursor: Option<U64>, limit_to: Option<u16>) -> Page<Dispute> {
+        // Disputes are never removed, so their positions do not change
+        vector_page(self.disputes.values_as_vector(), cursor, limit_to)
+    }
+
+    // Returns accounts allowed to rule on disputes
+    pub fn get_arbiters(&self) -> Vec<AccountId> {
+        self.arbiters.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{accept_role, add_habit, register, set_context, DAY, NEAR, OWNER, START};
+    use std::str::FromStr;
+
+    // Creates contract with one accepted habit of roman with josef as beneficiary
+    fn setup() -> (StickyHabitsContract, String) {
+        set_context(OWNER, 1, START);
+        let mut contract =
+            StickyHabitsContract::init(OWNER.parse().unwrap(), U64(500), U64(DAY), U64(DAY));
+        contract
+            .add_arbiter(AccountId::from_str("alice").unwrap())
+            .unwrap();
+        contract.set_dispute_period(U64(DAY)).unwrap();
+
+        register(&mut contract, "roman");
+        let id = add_habit(
+            &mut contract,
+            "roman",
+            "Do 15 push-ups everyday",
+            "josef",
+            21 * NEAR,
+        );
+        accept_role(&mut contract, "roman", 0, START);
+
+        (contract, id)
+    }
+
+    #[test]
+    fn overrides_rejection() {
+        let (mut contract, id) = setup();
+
+        set_context("josef", 0, START);
+        contract
+            .reject_habit_by_id(id.clone(), "No push-ups seen".to_string())
+            .unwrap();
+
+        set_context("roman", NEAR / 2, START);
+        assert_eq!(
+            contract.open_dispute(id.clone()),
+            Err(StickyHabitsError::InvalidDisputeBond(NEAR))
+        );
+        set_context("roman", NEAR, START);
+        contract.open_dispute(id.clone()).unwrap();
+        assert_eq!(
+            contract.get_habit(id.clone()).unwrap().status,
+            HabitStatus::Disputed
+        );
+        assert_eq!(
+            contract.open_dispute(id.clone()),
+            Err(StickyHabitsError::DisputeAlreadyOpen)
+        );
+
+        // Deposit stays locked until the ruling
+        set_context("josef", 0, 1662312790000000001);
+        assert_eq!(
+            contract.unlock_deposit_by_id(id.clone()),
+            Err(StickyHabitsError::InvalidStatus(HabitStatus::Disputed))
+        );
+        assert_eq!(
+            contract.resolve_dispute(id.clone(), true),
+            Err(StickyHabitsError::NotArbiter)
+        );
+
+        set_context("alice", 0, 1662312790000000001);
+        contract.resolve_dispute(id.clone(), true).unwrap();
+        let logs = near_sdk::test_utils::get_logs();
+        assert!(logs[0].contains(r#""event":"dispute_resolved""#));
+        assert!(logs[0].contains(r#""bond_to":"roman""#));
+        assert!(logs[1].contains(r#""event":"deposit_unlocked""#));
+
+        assert_eq!(
+            contract.get_habit(id.clone()).unwrap().status,
+            HabitStatus::Refunded
+        );
+        let ruling = contract.get_dispute(id.clone()).unwrap().ruling.unwrap();
+        assert!(ruling.approved);
+        assert_eq!(
+            contract.resolve_dispute(id, false),
+            Err(StickyHabitsError::DisputeAlreadyResolved)
+        );
+        assert_eq!(contract.balance, 0);
+    }
+
+    #[test]
+    fn pays_lost_bond_to_arbiter() {
+        let (mut contract, id) = setup();
+
+        // Beneficiary contests habit the user claims to have acquired and loses
+        set_context("josef", NEAR, START);
+        contract.open_dispute(id.clone()).unwrap();
+
+        set_context("alice", 0, START);
+        contract.resolve_dispute(id, true).unwrap();
+        let logs = near_sdk::test_utils::get_logs();
+        assert!(logs[0].contains(r#""bond_to":"alice""#));
+        assert_eq!(
+            contract.get_claimable("alice".parse().unwrap(), None),
+            U128(NEAR)
+        );
+    }
+
+    #[test]
+    fn forfeits_to_beneficiary() {
+        let (mut contract, id) = setup();
+
+        set_context("josef", NEAR, START);
+        contract.open_dispute(id.clone()).unwrap();
+        set_context("alice", 0, START);
+        contract.resolve_dispute(id.clone(), false).unwrap();
+        assert_eq!(
+            contract.get_habit(id).unwrap().status,
+            HabitStatus::Forfeited
+        );
+        assert_eq!(contract.get_disputes(None, None).items.len(), 1);
+    }
+
+    #[test]
+    fn closes_dispute_period() {
+        let (mut contract, id) = setup();
+
+        set_context("maria", NEAR, START);
+        assert_eq!(
+            contract.open_dispute(id.clone()),
+            Err(StickyHabitsError::NotUserOrBeneficiary)
+        );
+        set_context("roman", NEAR, START + 3 * DAY);
+        assert_eq!(
+            contract.open_dispute(id.clone()),
+            Err(StickyHabitsError::DisputePeriodOver)
+        );
+        assert!(contract.get_disputes(None, None).items.is_empty());
+
+        set_context(OWNER, 1, START);
+        contract
+            .remove_arbiter(AccountId::from_str("alice").unwrap())
+            .unwrap();
+        set_context("roman", NEAR, START);
+        assert_eq!(
+            contract.open_dispute(id),
+            Err(StickyHabitsError::NoArbiters)
+        );
+    }
+
+    #[test]
+    fn settles_without_ruling() {
+        let (mut contract, id) = setup();
+
+        set_context("josef", 0, START);
+        contract
+            .reject_habit_by_id(id.clone(), "No push-ups seen".to_string())
+            .unwrap();
+        set_context("roman", NEAR, START);
+        contract.open_dispute(id.clone()).unwrap();
+
+        // Arbiters have a week to rule
+        set_context("maria", 0, START + 2 * DAY);
+        assert_eq!(
+            contract.expire_dispute(id.clone()),
+            Err(StickyHabitsError::RulingPeriodNotOver)
+        );
+        set_context("alice", 0, START + 8 * DAY);
+        assert_eq!(
+            contract.resolve_dispute(id.clone(), true),
+            Err(StickyHabitsError::RulingPeriodOver)
+        );
+
+        // Anyone closes the dispute, the bond goes back to the opener
+        set_context("maria", 0, START + 8 * DAY);
+        contract.expire_dispute(id.clone()).unwrap();
+        let logs = near_sdk::test_utils::get_logs();
+        assert!(logs[0].contains(r#""event":"dispute_expired""#));
+        assert_eq!(
+            contract.get_habit(id.clone()).unwrap().status,
+            HabitStatus::Rejected
+        );
+        assert_eq!(
+            contract.get_claimable("roman".parse().unwrap(), None),
+            U128(NEAR)
+        );
+        assert_eq!(
+            contract.expire_dispute(id.clone()),
+            Err(StickyHabitsError::DisputeAlreadyResolved)
+        );
+
+        // Dispute period is extended by the 8 days the habit was disputed
+        set_context("josef", 0, START + 9 * DAY);
+        assert_eq!(
+            contract.unlock_deposit_by_id(id.clone()),
+            Err(StickyHabitsError::TooEarlyToUnlock)
+        );
+        set_context("josef", 0, START + 9 * DAY + 1);
+        contract.unlock_deposit_by_id(id.clone()).unwrap();
+        assert_eq!(
+            contract.get_habit(id).unwrap().status,
+            HabitStatus::Forfeited
+        );
+        assert_eq!(contract.balance, 0);
+    }
+
+    #[test]
+    fn keeps_approval_window_without_ruling() {
+        let (mut contract, _) = setup();
+        let beneficiaries = ["josef", "maria", "pavel"];
+
+        set_context("roman", 21 * NEAR, START);
+        contract
+            .add_group_habit(
+                "Read 10 pages every evening".to_string(),
+                U64(0),
+                beneficiaries.iter().map(|b| b.parse().unwrap()).collect(),
+                2,
+                None,
+            )
+            .unwrap();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[1]
+            .id
+            .clone();
+        accept_role(&mut contract, "roman", 1, START);
+
+        // Single beneficiary disputes the habit right after the deadline
+        set_context("josef", NEAR, START + DAY + 1);
+        contract.open_dispute(id.clone()).unwrap();
+
+        // Expiry does not forfeit the habit, the others still have their approval window
+        set_context("josef", 0, START + 9 * DAY);
+        contract.expire_dispute(id.clone()).unwrap();
+        assert_eq!(
+            contract.get_habit(id.clone()).unwrap().status,
+            HabitStatus::Pending
+        );
+        assert_eq!(
+            contract.unlock_deposit_by_id(id.clone()),
+            Err(StickyHabitsError::TooEarlyToUnlock)
+        );
+        for beneficiary in ["maria", "pavel"] {
+            set_context(beneficiary, 0, START + 9 * DAY);
+            contract.approve_habit_by_id(id.clone()).unwrap();
+        }
+        assert_eq!(
+            contract.get_habit(id).unwrap().status,
+            HabitStatus::Approved
+        );
+    }
+
+    #[test]
+    fn restores_status_without_ruling() {
+        let (mut contract, id) = setup();
+
+        set_context(OWNER, 1, START);
+        contract.set_ruling_period(U64(DAY / 24)).unwrap();
+        set_context("josef", NEAR, START);
+        let available = contract.storage_deposit(None, None).unwrap().available;
+        let roman_available = contract
+            .storage_balance_of("roman".parse().unwrap())
+            .unwrap()
+            .available;
+        contract.open_dispute(id.clone()).unwrap();
+        // Dispute record is paid by the beneficiary who opened it
+        assert!(
+            contract
+                .storage_balance_of("josef".parse().unwrap())
+                .unwrap()
+                .available
+                .0
+                < available.0
+        );
+        assert_eq!(
+            contract
+                .storage_balance_of("roman".parse().unwrap())
+                .unwrap()
+                .available,
+            roman_available
+        );
+        assert_eq!(
+            contract.get_dispute(id.clone()).unwrap().previous_status,
+            HabitStatus::Pending
+        );
+
+        // Habit is not unlockable yet, so it goes on as before the dispute
+        set_context("roman", 0, START + DAY / 12);
+        contract.expire_dispute(id.clone()).unwrap();
+        assert_eq!(
+            contract.get_habit(id.clone()).unwrap().status,
+            HabitStatus::Pending
+        );
+        assert_eq!(
+            contract.get_claimable("josef".parse().unwrap(), None),
+            U128(NEAR)
+        );
+        set_context("josef", NEAR, START + DAY / 12);
+        assert_eq!(
+            contract.open_dispute(id),
+            Err(StickyHabitsError::DisputeAlreadyOpen)
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use near_sdk::{env, AccountId, Balance, FunctionError};
+use std::fmt;
+
+use crate::HabitStatus;
+
+// Errors returned by contract methods. Each failed call panics with "<CODE>: <message>",
+// where CODE is the machine-readable part clients should match on.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StickyHabitsError {
+    AlreadyInitialized,
+    NotInitialized,
+    NotOwner,
+    NotProposedOwner,
+    InvalidDevFee(u64),
+    InvalidCancellationPenalty(u64),
+    SameUserAndBeneficiary,
+    InsufficientDeposit(Balance),
+    DuplicateHabitId(String),
+    EmptyEvidence,
+    NotHabitOwner,
+    NotBeneficiary,
+    NotUserOrBeneficiary,
+    UserHasNoHabits(AccountId),
+    HabitNotFound(String),
+    IndexOutOfRange(u64),
+    OutsideApprovalWindow,
+    TooEarlyToUnlock,
+    AlreadySettled,
+    EvidenceAlreadySubmitted,
+    CancellationPeriodOver,
+    BeneficiaryNotAccepted,
+    BeneficiaryAlreadyAccepted,
+    AcceptancePeriodOver,
+    InvalidStatus(HabitStatus),
+    EmptyRejectionReason,
+    RejectionPeriodOver,
+    NotArbiter,
+    InvalidDisputeBond(Balance),
+    DisputeAlreadyOpen,
+    DisputeAlreadyResolved,
+    DisputeNotFound(String),
+    DisputePeriodOver,
+    NoArbiters,
+    RulingPeriodOver,
+    RulingPeriodNotOver,
+    TooManyBeneficiaries(usize),
+    DuplicateBeneficiary(AccountId),
+    InvalidApprovalThreshold(u16),
+    AlreadyVoted,
+    InvalidPeriods(u16),
+    PeriodicHabit,
+    NotPeriodicHabit,
+    CheckInPeriodOver,
+    MissingCheckIn(u16),
+    CheckInAlreadyApproved(u16),
+    InvalidDigest(String),
+    NoEvidence,
+    DescriptionTooLong(u32),
+    EvidenceTooLong(u32),
+    UnsupportedEvidenceUri(String),
+    StorageNotRegistered(AccountId),
+    InsufficientStorageBalance(Balance),
+    StorageInUse,
+    OneYoctoRequired,
+    TokenNotWhitelisted(AccountId),
+    InvalidMessage(String),
+    NothingToWithdraw,
+    InsufficientClaimable(Balance),
+    Insolvent(Balance),
+    UnsupportedStateVersion(u16),
+}
+
+impl StickyHabitsError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::AlreadyInitialized => "ALREADY_INITIALIZED",
+            Self::NotInitialized => "NOT_INITIALIZED",
+            Self::NotOwner => "NOT_OWNER",
+            Self::NotProposedOwner => "NOT_PROPOSED_OWNER",
+            Self::InvalidDevFee(_) => "INVALID_DEV_FEE",
+            Self::InvalidCancellationPenalty(_) => "INVALID_CANCELLATION_PENALTY",
+            Self::SameUserAndBeneficiary => "SAME_USER_AND_BENEFICIARY",
+            Self::InsufficientDeposit(_) => "INSUFFICIENT_DEPOSIT",
+            Self::DuplicateHabitId(_) => "DUPLICATE_HABIT_ID",
+            Self::EmptyEvidence => "EMPTY_EVIDENCE",
+            Self::NotHabitOwner => "NOT_HABIT_OWNER",
+            Self::NotBeneficiary => "NOT_BENEFICIARY",
+            Self::NotUserOrBeneficiary => "NOT_USER_OR_BENEFICIARY",
+            Self::UserHasNoHabits(_) => "USER_HAS_NO_HABITS",
+            Self::HabitNotFound(_) => "HABIT_NOT_FOUND",
+            Self::IndexOutOfRange(_) => "INDEX_OUT_OF_RANGE",
+            Self::OutsideApprovalWindow => "OUTSIDE_APPROVAL_WINDOW",
+            Self::TooEarlyToUnlock => "TOO_EARLY_TO_UNLOCK",
+            Self::AlreadySettled => "ALREADY_SETTLED",
+            Self::EvidenceAlreadySubmitted => "EVIDENCE_ALREADY_SUBMITTED",
+            Self::CancellationPeriodOver => "CANCELLATION_PERIOD_OVER",
+            Self::BeneficiaryNotAccepted => "BENEFICIARY_NOT_ACCEPTED",
+            Self::BeneficiaryAlreadyAccepted => "BENEFICIARY_ALREADY_ACCEPTED",
+            Self::AcceptancePeriodOver => "ACCEPTANCE_PERIOD_OVER",
+            Self::InvalidStatus(_) => "INVALID_STATUS",
+            Self::EmptyRejectionReason => "EMPTY_REJECTION_REASON",
+            Self::RejectionPeriodOver => "REJECTION_PERIOD_OVER",
+            Self::NotArbiter => "NOT_ARBITER",
+            Self::InvalidDisputeBond(_) => "INVALID_DISPUTE_BOND",
+            Self::DisputeAlreadyOpen => "DISPUTE_ALREADY_OPEN",
+            Self::DisputeAlreadyResolved => "DISPUTE_ALREADY_RESOLVED",
+            Self::DisputeNotFound(_) => "DISPUTE_NOT_FOUND",
+            Self::DisputePeriodOver => "DISPUTE_PERIOD_OVER",
+            Self::NoArbiters => "NO_ARBITERS",
+            Self::RulingPeriodOver => "RULING_PERIOD_OVER",
+            Self::RulingPeriodNotOver => "RULING_PERIOD_NOT_OVER",
+            Self::TooManyBeneficiaries(_) => "TOO_MANY_BENEFICIARIES",
+            Self::DuplicateBeneficiary(_) => "DUPLICATE_BENEFICIARY",
+            Self::InvalidApprovalThreshold(_) => "INVALID_APPROVAL_THRESHOLD",
+            Self::AlreadyVoted => "ALREADY_VOTED",
+            Self::InvalidPeriods(_) => "INVALID_PERIODS",
+            Self::PeriodicHabit => "PERIODIC_HABIT",
+            Self::NotPeriodicHabit => "NOT_PERIODIC_HABIT",
+            Self::CheckInPeriodOver => "CHECK_IN_PERIOD_OVER",
+            Self::MissingCheckIn(_) => "MISSING_CHECK_IN",
+            Self::CheckInAlreadyApproved(_) => "CHECK_IN_ALREADY_APPROVED",
+            Self::InvalidDigest(_) => "INVALID_DIGEST",
+            Self::NoEvidence => "NO_EVIDENCE",
+            Self::DescriptionTooLong(_) => "DESCRIPTION_TOO_LONG",
+            Self::EvidenceTooLong(_) => "EVIDENCE_TOO_LONG",
+            Self::UnsupportedEvidenceUri(_) => "UNSUPPORTED_EVIDENCE_URI",
+            Self::StorageNotRegistered(_) => "STORAGE_NOT_REGISTERED",
+            Self::InsufficientStorageBalance(_) => "INSUFFICIENT_STORAGE_BALANCE",
+            Self::StorageInUse => "STORAGE_IN_USE",
+            Self::OneYoctoRequired => "ONE_YOCTO_REQUIRED",
+            Self::TokenNotWhitelisted(_) => "TOKEN_NOT_WHITELISTED",
+            Self::InvalidMessage(_) => "INVALID_MESSAGE",
+            Self::NothingToWithdraw => "NOTHING_TO_WITHDRAW",
+            Self::InsufficientClaimable(_) => "INSUFFICIENT_CLAIMABLE",
+            Self::Insolvent(_) => "INSOLVENT",
+            Self::UnsupportedStateVersion(_) => "UNSUPPORTED_STATE_VERSION",
+        }
+    }
+}
+
+impl fmt::Display for StickyHabitsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.code())?;
+        match self {
+            Self::AlreadyInitialized => write!(f, "Already initialized"),
+            Self::NotInitialized => write!(f, "Not initialized yet"),
+            Self::NotOwner => write!(f, "Only contract owner can call this method"),
+            Self::NotProposedOwner => {
+                write!(f, "Only proposed owner can accept the ownership")
+            }
+            Self::InvalidDevFee(bps) => {
+                write!(f, "Developer fee of {} basis points is out of range", bps)
+            }
+            Self::InvalidCancellationPenalty(bps) => write!(
+                f,
+                "Cancellation penalty of {} basis points is out of range",
+                bps
+            ),
+            Self::SameUserAndBeneficiary => {
+                write!(f, "User and Beneficiary should be different accounts")
+            }
+            Self::InsufficientDeposit(required) => {
+                write!(f, "Attach at least {} yoctoNEAR", required)
+            }
+            Self::DuplicateHabitId(id) => write!(f, "Habit id {} already exists", id),
+            Self::EmptyEvidence => write!(f, "Evidence cannot be empty"),
+            Self::NotHabitOwner => write!(f, "Only the user of the habit can do this"),
+            Self::NotBeneficiary => write!(f, "Only a beneficiary of the habit can do this"),
+            Self::NotUserOrBeneficiary => {
+                write!(f, "Only the user or a beneficiary of the habit can do this")
+            }
+            Self::UserHasNoHabits(user) => write!(f, "User {} has no habit yet", user),
+            Self::HabitNotFound(id) => write!(f, "Habit {} does not exist", id),
+            Self::IndexOutOfRange(index) => write!(f, "Index {} is out of range", index),
+            Self::OutsideApprovalWindow => write!(
+                f,
+                "Habit can be approved only between deadline and end of approval grace period"
+            ),
+            Self::TooEarlyToUnlock => {
+                write!(f, "Deposit can be unlocked after approval grace period")
+            }
+            Self::AlreadySettled => write!(f, "Habit deposit has already been settled"),
+            Self::EvidenceAlreadySubmitted => {
+                write!(f, "Habit with evidence cannot be cancelled")
+            }
+            Self::CancellationPeriodOver => {
+                write!(f, "Habit can be cancelled only shortly after creation")
+            }
+            Self::BeneficiaryNotAccepted => {
+                write!(f, "Beneficiary has not accepted the role yet")
+            }
+            Self::BeneficiaryAlreadyAccepted => {
+                write!(f, "Beneficiary has already accepted the role")
+            }
+            Self::AcceptancePeriodOver => {
+                write!(f, "Beneficiary role can no longer be accepted")
+            }
+            Self::InvalidStatus(status) => {
+                write!(f, "Action is not allowed for habit in {:?} status", status)
+            }
+            Self::EmptyRejectionReason => write!(f, "Rejection reason cannot be empty"),
+            Self::RejectionPeriodOver => write!(
+                f,
+                "Habit can be rejected only until end of approval grace period"
+            ),
+            Self::NotArbiter => write!(f, "Only arbiter can resolve disputes"),
+            Self::InvalidDisputeBond(bond) => {
+                write!(f, "Attach exactly {} yoctoNEAR as dispute bond", bond)
+            }
+            Self::DisputeAlreadyOpen => write!(f, "Habit is already disputed"),
+            Self::DisputeAlreadyResolved => write!(f, "Dispute has already been resolved"),
+            Self::DisputeNotFound(id) => write!(f, "Habit {} has no dispute", id),
+            Self::DisputePeriodOver => {
+                write!(
+                    f,
+                    "Habit can be disputed only before the deposit is unlockable"
+                )
+            }
+            Self::NoArbiters => write!(f, "There are no arbiters to rule on disputes"),
+            Self::RulingPeriodOver => write!(f, "Dispute can be resolved only until its deadline"),
+            Self::RulingPeriodNotOver => {
+                write!(
+                    f,
+                    "Dispute can be closed without ruling only after its deadline"
+                )
+            }
+            Self::TooManyBeneficiaries(max) => {
+                write!(f, "Habit can have at most {} beneficiaries", max)
+            }
+            Self::DuplicateBeneficiary(beneficiary) => {
+                write!(f, "Beneficiary {} is listed more than once", beneficiary)
+            }
+            Self::InvalidApprovalThreshold(threshold) => write!(
+                f,
+                "Approval threshold {} is out of range of beneficiaries",
+                threshold
+            ),
+            Self::AlreadyVoted => write!(f, "Beneficiary has already voted on the habit"),
+            Self::InvalidPeriods(periods) => {
+                write!(f, "Number of periods {} is out of range", periods)
+            }
+            Self::PeriodicHabit => {
+                write!(f, "Periodic habit is checked in and approved per period")
+            }
+            Self::NotPeriodicHabit => write!(f, "Habit has no periods"),
+            Self::CheckInPeriodOver => write!(f, "All periods of the habit are over"),
+            Self::MissingCheckIn(period) => {
+                write!(f, "Period {} has no check-in to approve", period)
+            }
+            Self::CheckInAlreadyApproved(period) => {
+                write!(f, "Check-in of period {} is already approved", period)
+            }
+            Self::InvalidDigest(digest) => {
+                write!(f, "Digest {} is not a hex encoded SHA-256 hash", digest)
+            }
+            Self::NoEvidence => write!(f, "Habit has no evidence"),
+            Self::DescriptionTooLong(max) => {
+                write!(f, "Description cannot be longer than {} bytes", max)
+            }
+            Self::EvidenceTooLong(max) => {
+                write!(f, "Evidence cannot be longer than {} bytes", max)
+            }
+            Self::UnsupportedEvidenceUri(uri) => write!(
+                f,
+                "Evidence {} is not a {} link",
+                uri,
+                crate::evidence::ALLOWED_URI_SCHEMES.join(", ")
+            ),
+            Self::StorageNotRegistered(account) => write!(
+                f,
+                "Account {} has to deposit storage with storage_deposit first",
+                account
+            ),
+            Self::InsufficientStorageBalance(shortfall) => {
+                write!(f, "Storage balance is short of {} yoctoNEAR", shortfall)
+            }
+            Self::StorageInUse => {
+                write!(f, "Account still uses storage for its habits and claims")
+            }
+            Self::OneYoctoRequired => write!(f, "Requires attached deposit of exactly 1 yoctoNEAR"),
+            Self::TokenNotWhitelisted(token) => {
+                write!(f, "Token {} is not accepted as stake", token)
+            }
+            Self::InvalidMessage(error) => write!(f, "Cannot parse message: {}", error),
+            Self::NothingToWithdraw => write!(f, "Nothing to withdraw"),
+            Self::InsufficientClaimable(shortfall) => {
+                write!(f, "Claimable amount is short of {}", shortfall)
+            }
+            Self::Insolvent(shortfall) => {
+                write!(f, "Account balance is short of {} yoctoNEAR", shortfall)
+            }
+            Self::UnsupportedStateVersion(version) => {
+                write!(f, "Cannot migrate state of version {}", version)
+            }
+        }
+    }
+}
+
+impl FunctionError for StickyHabitsError {
+    fn panic(&self) -> ! {
+        env::panic_str(&self.to_string())
+    }
+}
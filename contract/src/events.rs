@@ -0,0 +1,243 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+use near_sdk::{log, AccountId};
+
+// NEP-297 standard name and version of the events emitted by this contract
+pub const EVENT_STANDARD: &str = "stickyhabits";
+pub const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+// Habit lifecycle transitions, serialized as "event" and "data" fields of NEP-297 log
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum HabitEvent {
+    HabitCreated(Vec<HabitCreatedData>),
+    EvidenceUpdated(Vec<EvidenceUpdatedData>),
+    HabitApproved(Vec<HabitApprovedData>),
+    DepositUnlocked(Vec<DepositUnlockedData>),
+    DepositForfeited(Vec<DepositForfeitedData>),
+    HabitCancelled(Vec<HabitCancelledData>),
+    BeneficiaryAccepted(Vec<BeneficiaryAcceptedData>),
+    BeneficiaryDeclined(Vec<BeneficiaryDeclinedData>),
+    HabitRejected(Vec<HabitRejectedData>),
+    DisputeOpened(Vec<DisputeOpenedData>),
+    DisputeResolved(Vec<DisputeResolvedData>),
+    DisputeExpired(Vec<DisputeExpiredData>),
+    VoteRecorded(Vec<VoteRecordedData>),
+    CheckInSubmitted(Vec<CheckInSubmittedData>),
+    CheckInApproved(Vec<CheckInApprovedData>),
+    HabitArchived(Vec<HabitArchivedData>),
+    Withdrawn(Vec<WithdrawalData>),
+    WithdrawalFailed(Vec<WithdrawalData>),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HabitCreatedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub description: String,
+    pub deadline: U64,
+    pub deposit: U128,
+    pub token: Option<AccountId>,
+    pub beneficiaries: Vec<AccountId>,
+    pub approval_threshold: u16,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EvidenceUpdatedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub evidence: String,
+    pub digest: Option<String>,
+    pub late: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HabitApprovedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub beneficiary: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositUnlockedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositForfeitedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub beneficiary: AccountId,
+    pub to_beneficiary: U128,
+    pub to_developer: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HabitCancelledData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub refund: U128,
+    pub penalty: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BeneficiaryAcceptedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub beneficiary: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BeneficiaryDeclinedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub beneficiary: AccountId,
+    pub refund: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HabitRejectedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub beneficiary: AccountId,
+    pub reason: String,
+    pub unlockable_at: U64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DisputeOpenedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub opened_by: AccountId,
+    pub bond: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DisputeResolvedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub arbiter: AccountId,
+    pub approved: bool,
+    pub bond_to: AccountId,
+}
+
+// Dispute nobody ruled on in time, the habit is back in its previous status
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DisputeExpiredData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub opened_by: AccountId,
+    pub bond: U128,
+}
+
+// Beneficiary's vote which did not decide the habit yet
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VoteRecordedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub beneficiary: AccountId,
+    pub approved: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CheckInSubmittedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub period: u16,
+    pub evidence: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CheckInApprovedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub period: u16,
+    pub beneficiary: AccountId,
+}
+
+// Settled habit moved out of user's habits, deleted unless the summary is kept
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HabitArchivedData {
+    pub user: AccountId,
+    pub habit_id: String,
+    pub summary_kept: bool,
+}
+
+// Claimable amount of the account paid out to the receiver, credited back to the account
+// if the transfer fails
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawalData {
+    pub account: AccountId,
+    pub receiver: AccountId,
+    pub token: Option<AccountId>,
+    pub amount: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a HabitEvent,
+}
+
+impl HabitEvent {
+    // Logs the event as EVENT_JSON:{"standard":..,"version":..,"event":..,"data":[..]}
+    pub fn emit(&self) {
+        let event_log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_STANDARD_VERSION,
+            event: self,
+        };
+        log!(
+            "EVENT_JSON:{}",
+            serde_json::to_string(&event_log).expect("Cannot serialize event")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils;
+    use std::str::FromStr;
+
+    #[test]
+    fn formats_nep297_log() {
+        HabitEvent::HabitApproved(vec![HabitApprovedData {
+            user: AccountId::from_str("roman").unwrap(),
+            habit_id: "abc".to_string(),
+            beneficiary: AccountId::from_str("josef").unwrap(),
+        }])
+        .emit();
+
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"stickyhabits","version":"1.0.0","event":"habit_approved","data":[{"user":"roman","habit_id":"abc","beneficiary":"josef"}]}"#
+            ]
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::Vector;
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::pagination::{paginate, vector_page, Page};
+use crate::{Habit, StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+// Entry of the append-only evidence history of a habit
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Evidence {
+    pub uri: String,
+    // Hash of the content behind the uri, if the user committed to one
+    pub hash: Option<String>,
+    pub submitted_at: U64,
+    // Added after the habit's deadline
+    pub late: bool,
+}
+
+// Schemes of evidence links, the content has to be publicly retrievable
+pub const ALLOWED_URI_SCHEMES: [&str; 3] = ["https", "ipfs", "ar"];
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // Appends evidence to the habit, earlier entries are kept. Hash is hex encoded
+    // SHA-256 digest of the content.
+    #[payable]
+    #[handle_result]
+    pub fn add_evidence(
+        &mut self,
+        id: String,
+        uri: String,
+        hash: Option<String>,
+    ) -> Result<(), StickyHabitsError> {
+        let (user, index) = self.habit_location(&id)?;
+        self.internal_update_evidence(user, index, uri, hash)
+    }
+
+    // Returns page of evidence history of habit with given id, oldest first
+    pub fn get_evidence(
+        &self,
+        id: String,
+        cursor: Option<U64>,
+        limit_to: Option<u16>,
+    ) -> Page<Evidence> {
+        match self.evidence.get(&id) {
+            Some(history) => vector_page(&history, cursor, limit_to),
+            None => paginate(std::iter::empty(), limit_to),
+        }
+    }
+
+    // Checks the content under review against the digest committed with the latest evidence
+    #[handle_result]
+    pub fn verify_evidence(
+        &self,
+        user: AccountId,
+        at_index: u16,
+        digest: String,
+    ) -> Result<bool, StickyHabitsError> {
+        let (_, habit) = self.habit_at(&user, u64::from(at_index))?;
+        let latest = self
+            .evidence
+            .get(&habit.id)
+            .and_then(|history| history.iter().next_back())
+            .ok_or(StickyHabitsError::NoEvidence)?;
+
+        Ok(latest.hash == parse_digest(Some(digest))?)
+    }
+}
+
+// Validates hex encoded SHA-256 digest, stored in lower case
+pub(crate) fn parse_digest(digest: Option<String>) -> Result<Option<String>, StickyHabitsError> {
+    match digest {
+        Some(digest) if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Err(StickyHabitsError::InvalidDigest(digest))
+        }
+        digest => Ok(digest.map(|d| d.to_ascii_lowercase())),
+    }
+}
+
+impl StickyHabitsContract {
+    // Checks evidence link against the length limit and allowed schemes
+    pub(crate) fn validate_evidence(&self, uri: &str) -> Result<(), StickyHabitsError> {
+        if uri.is_empty() {
+            return Err(StickyHabitsError::EmptyEvidence);
+        }
+        if uri.len() > self.max_evidence_length as usize {
+            return Err(StickyHabitsError::EvidenceTooLong(self.max_evidence_length));
+        }
+        match uri.split_once("://") {
+            Some((scheme, rest))
+                if !rest.is_empty()
+                    && ALLOWED_URI_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()) =>
+            {
+                Ok(())
+            }
+            _ => Err(StickyHabitsError::UnsupportedEvidenceUri(uri.to_string())),
+        }
+    }
+
+    // Appends evidence to the history of the habit, flagged as late after the deadline
+    pub(crate) fn record_evidence(
+        &mut self,
+        habit: &Habit,
+        uri: String,
+        hash: Option<String>,
+    ) -> Evidence {
+        let current_time = env::block_timestamp();
+        let entry = Evidence {
+            uri,
+            hash,
+            submitted_at: U64(current_time),
+            late: current_time > u64::from(habit.deadline),
+        };
+
+        let mut history = match self.evidence.get(&habit.id) {
+            Some(v) => v,
+            None => Vector::new(("vector-e-id-".to_string() + &habit.id).as_bytes().to_vec()),
+        };
+        history.push(&entry);
+        self.evidence.insert(&habit.id, &history);
+
+        entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{add_habit, register, set_context, DAY, NEAR, OWNER, START};
+    use crate::HabitStatus;
+    use near_sdk::AccountId;
+    use std::str::FromStr;
+
+    // sha256 of "push-ups"
+    const CONTENT_DIGEST: &str = "19c69ed64c86a246e7ed055ea934601c6551dd60a987ea6b967b44e75f6b14d4";
+
+    #[test]
+    fn keeps_evidence_history() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        let id = add_habit(
+            &mut contract,
+            "roman",
+            "Do 15 push-ups everyday",
+            "josef",
+            20 * NEAR,
+        );
+        assert!(contract
+            .get_evidence(id.clone(), None, None)
+            .items
+            .is_empty());
+
+        set_context("roman", 0, START + DAY);
+        contract
+            .update_evidence_by_id(
+                id.clone(),
+                "https://www.icloud.com/first.mov".to_string(),
+                None,
+            )
+            .unwrap();
+        set_context("maria", 0, START + DAY);
+        assert_eq!(
+            contract.add_evidence(id.clone(), "https://www.icloud.com/x.mov".to_string(), None),
+            Err(StickyHabitsError::NotHabitOwner)
+        );
+
+        // Evidence after the deadline is accepted, but flagged
+        set_context("roman", 0, START + 22 * DAY);
+        contract
+            .add_evidence(
+                id.clone(),
+                "https://www.icloud.com/second.mov".to_string(),
+                Some(CONTENT_DIGEST.to_string()),
+            )
+            .unwrap();
+
+        let history = contract.get_evidence(id.clone(), None, None).items;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].uri, "https://www.icloud.com/first.mov");
+        assert_eq!(history[0].submitted_at, U64(START + DAY));
+        assert!(!history[0].late);
+        assert_eq!(history[1].hash, Some(CONTENT_DIGEST.to_string()));
+        assert!(history[1].late);
+        let page = contract.get_evidence(id.clone(), None, Some(1));
+        assert_eq!(page.next_cursor, Some(U64(0)));
+        let page = contract.get_evidence(id.clone(), page.next_cursor, Some(1));
+        assert_eq!(page.items[0].uri, "https://www.icloud.com/second.mov");
+        assert_eq!(page.next_cursor, None);
+
+        // Latest entry is shown on the habit
+        let habit = contract.get_habit(id).unwrap();
+        assert_eq!(habit.evidence, "https://www.icloud.com/second.mov");
+        assert_eq!(habit.status, HabitStatus::EvidenceSubmitted);
+    }
+
+    #[test]
+    fn verifies_evidence_digest() {
+        let mut contract = StickyHabitsContract::default();
+        let roman = AccountId::from_str("roman").unwrap();
+
+        register(&mut contract, "roman");
+        add_habit(
+            &mut contract,
+            "roman",
+            "Do 15 push-ups everyday",
+            "josef",
+            20 * NEAR,
+        );
+        assert_eq!(
+            contract.verify_evidence(roman.clone(), 0, CONTENT_DIGEST.to_string()),
+            Err(StickyHabitsError::NoEvidence)
+        );
+        assert_eq!(
+            contract.update_evidence(
+                roman.clone(),
+                0,
+                "https://www.icloud.com/myfile.mov".to_string(),
+                Some("abc".to_string())
+            ),
+            Err(StickyHabitsError::InvalidDigest("abc".to_string()))
+        );
+        contract
+            .update_evidence(
+                roman.clone(),
+                0,
+                "https://www.icloud.com/myfile.mov".to_string(),
+                Some(CONTENT_DIGEST.to_uppercase()),
+            )
+            .unwrap();
+
+        set_context("josef", 0, START);
+        assert_eq!(
+            contract.verify_evidence(roman.clone(), 0, sha256::digest("push-ups")),
+            Ok(true)
+        );
+        assert_eq!(
+            contract.verify_evidence(roman, 0, sha256::digest("sit-ups")),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn validates_evidence_uri() {
+        set_context(OWNER, 1, START);
+        let mut contract = StickyHabitsContract::init(
+            OWNER.parse().unwrap(),
+            U64(500),
+            U64(21 * DAY),
+            U64(15 * DAY),
+        );
+        contract.set_max_description_length(10).unwrap();
+        contract.set_max_evidence_length(48).unwrap();
+        let roman = AccountId::from_str("roman").unwrap();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, START);
+        assert_eq!(
+            contract.add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None
+            ),
+            Err(StickyHabitsError::DescriptionTooLong(10))
+        );
+        contract
+            .add_habit(
+                "Push-ups".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+
+        for uri in [
+            "http://www.icloud.com/myfile.mov",
+            "www.icloud.com",
+            "ipfs://",
+        ] {
+            assert_eq!(
+                contract.update_evidence(roman.clone(), 0, uri.to_string(), None),
+                Err(StickyHabitsError::UnsupportedEvidenceUri(uri.to_string()))
+            );
+        }
+        assert_eq!(
+            contract.update_evidence(
+                roman.clone(),
+                0,
+                "https://www.icloud.com/a/very/long/path/to/myfile.mov".to_string(),
+                None
+            ),
+            Err(StickyHabitsError::EvidenceTooLong(48))
+        );
+
+        let uri = "ipfs://bafybeigdyrzt5sfp7udm7hu76uh7y26nf3";
+        contract
+            .update_evidence(roman.clone(), 0, uri.to_string(), None)
+            .unwrap();
+        assert_eq!(
+            contract.get_habits_user(roman, None, None).items[0].evidence,
+            uri
+        );
+    }
+}
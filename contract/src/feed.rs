@@ -0,0 +1,268 @@
+use near_sdk::collections::TreeMap;
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::pagination::{paginate, Page};
+use crate::{Habit, StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+// Public habit with the user who made it and when it was created or settled
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeedItem {
+    pub user: AccountId,
+    pub habit: Habit,
+    pub at: U64,
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // User hides the habit from the public feeds or shows it again
+    #[handle_result]
+    pub fn set_habit_private(
+        &mut self,
+        id: String,
+        private: bool,
+    ) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+
+        let (user, index) = self.habit_location(&id)?;
+        if user != account {
+            return Err(StickyHabitsError::NotHabitOwner);
+        }
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+
+        habit.private = private;
+        let _updated = existing_habits.replace(index, &habit);
+
+        Ok(())
+    }
+
+    // Returns page of public habits, most recently created first
+    pub fn get_recent_habits(&self, cursor: Option<U64>, limit_to: Option<u16>) -> Page<FeedItem> {
+        self.feed_page(&self.recent_habits, cursor, limit_to)
+    }
+
+    // Returns page of public settled habits, most recently settled first
+    pub fn get_recent_settlements(
+        &self,
+        cursor: Option<U64>,
+        limit_to: Option<u16>,
+    ) -> Page<FeedItem> {
+        self.feed_page(&self.recent_settlements, cursor, limit_to)
+    }
+}
+
+impl StickyHabitsContract {
+    // Adds the habit created now to the creation feed
+    pub(crate) fn record_creation(&mut self, seq: u64, id: &str) {
+        self.recent_habits
+            .insert(&seq, &(id.to_string(), U64(env::block_timestamp())));
+    }
+
+    // Adds the habit settled now to the settlement feed, the habit keeps its key
+    pub(crate) fn record_settlement(&mut self, habit: &mut Habit) {
+        let seq = self.settlement_counter;
+        self.settlement_counter += 1;
+        self.recent_settlements
+            .insert(&seq, &(habit.id.clone(), U64(env::block_timestamp())));
+        habit.settlement_seq = Some(U64(seq));
+    }
+
+    // Removes entries of the archived habit from both feeds
+    pub(crate) fn remove_feed_entries(&mut self, habit: &Habit) {
+        self.recent_habits.remove(&u64::from(habit.seq));
+        if let Some(seq) = habit.settlement_seq {
+            self.recent_settlements.remove(&u64::from(seq));
+        }
+    }
+
+    // Walks the feed from the cursor towards the oldest entry. Cursor is the key of the last
+    // entry read, private habits are skipped.
+    fn feed_page(
+        &self,
+        feed: &TreeMap<u64, (String, U64)>,
+        cursor: Option<U64>,
+        limit_to: Option<u16>,
+    ) -> Page<FeedItem> {
+        let entries =
+            feed.iter_rev_from(cursor.map_or(u64::MAX, u64::from))
+                .map(|(key, (id, at))| {
+                    let item = self
+                        .habit_index
+                        .get(&id)
+                        .and_then(|(user, index)| {
+                            self.habits
+                                .get(&user)
+                                .and_then(|v| v.get(index))
+                                .map(|habit| FeedItem { user, habit, at })
+                        })
+                        .filter(|item| !item.habit.private);
+                    (key, item)
+                });
+
+        paginate(entries, limit_to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{register, set_context, NEAR, START};
+    use crate::HabitStatus;
+    use std::str::FromStr;
+
+    fn descriptions(page: &Page<FeedItem>) -> Vec<&str> {
+        page.items
+            .iter()
+            .map(|item| item.habit.description.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn lists_recent_habits() {
+        let mut contract = StickyHabitsContract::default();
+
+        for (i, user) in ["roman", "adam", "roman", "maria"].iter().enumerate() {
+            register(&mut contract, user);
+            set_context(user, 10 * NEAR, START + i as u64);
+            contract
+                .add_habit(
+                    format!("Habit {}", i),
+                    U64(0),
+                    AccountId::from_str("josef").unwrap(),
+                    None,
+                )
+                .unwrap();
+        }
+        let ids: Vec<String> = contract
+            .get_recent_habits(None, None)
+            .items
+            .iter()
+            .map(|item| item.habit.id.clone())
+            .collect();
+
+        // Newest first, pages continue from the cursor
+        let page = contract.get_recent_habits(None, Some(3));
+        assert_eq!(descriptions(&page), vec!["Habit 3", "Habit 2", "Habit 1"]);
+        assert_eq!(page.items[0].user.as_str(), "maria");
+        assert_eq!(page.items[0].at, U64(START + 3));
+        let page = contract.get_recent_habits(page.next_cursor, Some(3));
+        assert_eq!(descriptions(&page), vec!["Habit 0"]);
+        assert_eq!(page.next_cursor, None);
+
+        // Private habit is hidden from the feeds
+        set_context("adam", 0, START);
+        assert_eq!(
+            contract.set_habit_private(ids[0].clone(), true),
+            Err(StickyHabitsError::NotHabitOwner)
+        );
+        set_context("roman", 0, START);
+        contract.set_habit_private(ids[1].clone(), true).unwrap();
+        assert_eq!(
+            descriptions(&contract.get_recent_habits(None, None)),
+            vec!["Habit 3", "Habit 1", "Habit 0"]
+        );
+
+        // Cancelled habits are listed in order of settlement
+        set_context("roman", 0, START + 10);
+        contract.cancel_habit(ids[3].clone()).unwrap();
+        contract.cancel_habit(ids[1].clone()).unwrap();
+        set_context("maria", 0, START + 20);
+        contract.cancel_habit(ids[0].clone()).unwrap();
+        let page = contract.get_recent_settlements(None, None);
+        assert_eq!(descriptions(&page), vec!["Habit 3", "Habit 0"]);
+        assert_eq!(page.items[0].at, U64(START + 20));
+        assert_eq!(page.items[0].habit.status, HabitStatus::Cancelled);
+
+        set_context("roman", 0, START + 30);
+        contract.set_habit_private(ids[1].clone(), false).unwrap();
+        assert_eq!(
+            descriptions(&contract.get_recent_settlements(None, None)),
+            vec!["Habit 3", "Habit 2", "Habit 0"]
+        );
+    }
+
+    #[test]
+    fn prunes_archived_habits() {
+        let mut contract = StickyHabitsContract::default();
+        register(&mut contract, "roman");
+
+        for i in 0..3 {
+            set_context("roman", 10 * NEAR, START + i);
+            contract
+                .add_habit(
+                    format!("Habit {}", i),
+                    U64(0),
+                    AccountId::from_str("josef").unwrap(),
+                    // Habit can be kept out of the feeds from its creation
+                    Some(i == 1),
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            descriptions(&contract.get_recent_habits(None, None)),
+            vec!["Habit 2", "Habit 0"]
+        );
+
+        let id = contract.get_recent_habits(None, None).items[1]
+            .habit
+            .id
+            .clone();
+        set_context("roman", 0, START + 10);
+        contract.cancel_habit(id).unwrap();
+        assert_eq!(contract.recent_settlements.len(), 1);
+        let available = contract
+            .storage_balance_of(AccountId::from_str("roman").unwrap())
+            .unwrap()
+            .available;
+
+        // Archived habit leaves both feeds and its storage is released
+        contract.archive_settled_habits(Some(false), None).unwrap();
+        assert_eq!(
+            descriptions(&contract.get_recent_habits(None, None)),
+            vec!["Habit 2"]
+        );
+        assert_eq!(contract.recent_habits.len(), 2);
+        assert!(contract.get_recent_settlements(None, None).items.is_empty());
+        assert_eq!(contract.recent_settlements.len(), 0);
+        assert!(
+            contract
+                .storage_balance_of(AccountId::from_str("roman").unwrap())
+                .unwrap()
+                .available
+                .0
+                > available.0
+        );
+    }
+
+    #[test]
+    fn bounds_scan_of_private_habits() {
+        let mut contract = StickyHabitsContract::default();
+        set_context("roman", 5 * NEAR, START);
+        contract.storage_deposit(None, None).unwrap();
+
+        for i in 0..10 {
+            set_context("roman", NEAR, START + i);
+            contract
+                .add_habit(
+                    format!("Habit {}", i),
+                    U64(0),
+                    AccountId::from_str("josef").unwrap(),
+                    Some(i != 0),
+                )
+                .unwrap();
+        }
+
+        // Page stops after the scanned entries and the next one continues where it stopped
+        let page = contract.get_recent_habits(None, Some(1));
+        assert!(page.items.is_empty());
+        assert_eq!(page.next_cursor, Some(U64(6)));
+        let page = contract.get_recent_habits(page.next_cursor, Some(1));
+        assert!(page.items.is_empty());
+        assert_eq!(page.next_cursor, Some(U64(2)));
+        let page = contract.get_recent_habits(page.next_cursor, Some(1));
+        assert_eq!(descriptions(&page), vec!["Habit 0"]);
+        assert_eq!(page.next_cursor, None);
+    }
+}
@@ -1,12 +1,49 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{UnorderedMap, Vector};
+use near_sdk::collections::{TreeMap, UnorderedMap, UnorderedSet, Vector};
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{env, log, near_bindgen, AccountId, Balance, Promise};
+use near_sdk::{env, near_bindgen, AccountId, Balance, FunctionError, StorageUsage};
 use sha256::digest;
 use std::collections::HashMap;
-
-pub const STORAGE_COST: u128 = 1_000_000_000_000_000_000_000;
+use std::ops::Bound;
+
+pub use crate::accounting::Accounting;
+pub use crate::admin::Config;
+pub use crate::archive::HabitSummary;
+use crate::claims::Claim;
+pub use crate::dispute::{Dispute, Ruling};
+pub use crate::error::StickyHabitsError;
+use crate::events::{
+    BeneficiaryAcceptedData, BeneficiaryDeclinedData, DepositForfeitedData, DepositUnlockedData,
+    EvidenceUpdatedData, HabitApprovedData, HabitCancelledData, HabitCreatedData, HabitEvent,
+    HabitRejectedData, VoteRecordedData,
+};
+pub use crate::evidence::Evidence;
+pub use crate::feed::FeedItem;
+pub use crate::pagination::{Page, UserHabit};
+pub use crate::periodic::{Cadence, CheckIn, Schedule};
+pub use crate::settlement::Settlement;
+use crate::storage::StorageAccount;
+pub use crate::storage::{StorageBalance, StorageBalanceBounds};
+
+mod accounting;
+mod admin;
+mod archive;
+mod claims;
+mod dispute;
+mod error;
+pub mod events;
+mod evidence;
+mod feed;
+mod migration;
+mod pagination;
+mod periodic;
+mod settlement;
+mod storage;
+mod token;
+
+// Upper bound of beneficiaries of one habit, keeps settlement within gas limits
+pub const MAX_BENEFICIARIES: usize = 10;
 
 #[near_bindgen]
 #[derive(Serialize, Deserialize, Debug, BorshDeserialize, BorshSerialize)]
@@ -16,25 +53,203 @@ pub struct Habit {
     description: String,
     deadline: U64,
     deposit: U128,
-    beneficiary: AccountId,
+    beneficiaries: Vec<AccountId>,
     evidence: String,
+    status: HabitStatus,
+    created_at: U64,
+    beneficiary_accepted: bool,
+    terms: HabitTerms,
+    rejection: Option<Rejection>,
+    // Number of beneficiaries who have to approve the habit
+    approval_threshold: u16,
+    accepted_by: Vec<AccountId>,
+    votes: Vec<Vote>,
+    // Check-ins of periodic habit, None for habit with a single evidence
+    schedule: Option<Schedule>,
+    // NEP-141 token of the deposit, None for NEAR
+    token: Option<AccountId>,
+    // Creation order across all habits, used as pagination cursor
+    seq: U64,
+    // Hidden from the public feeds
+    private: bool,
+    // Key in the settlement feed, None until settled
+    settlement_seq: Option<U64>,
+    // Time spent under a dispute that expired without ruling, it extends the voting windows
+    disputed_for: U64,
+}
+
+impl Habit {
+    fn is_beneficiary(&self, account: &AccountId) -> bool {
+        self.beneficiaries.contains(account)
+    }
+
+    fn has_voted(&self, account: &AccountId) -> bool {
+        self.votes.iter().any(|v| &v.beneficiary == account)
+    }
+
+    fn approvals(&self) -> usize {
+        self.votes.iter().filter(|v| v.approved).count()
+    }
+
+    fn rejections(&self) -> usize {
+        self.votes.iter().filter(|v| !v.approved).count()
+    }
+
+    // Only habits migrated from V1 have no creation time, their storage was paid by
+    // the flat V1 fee
+    fn is_migrated(&self) -> bool {
+        self.created_at.0 == 0
+    }
+}
+
+// Lifecycle of a habit, transitions are enforced by the *_action helpers
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, BorshDeserialize, BorshSerialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum HabitStatus {
+    // Created, waiting for evidence
+    Pending,
+    EvidenceSubmitted,
+    // Beneficiary confirmed the habit was acquired, deposit goes back to the user on unlock
+    Approved,
+    // Beneficiary refused the evidence, deposit is forfeited on unlock
+    Rejected,
+    // Deposit returned to the user
+    Refunded,
+    // Deposit split between beneficiary and developer
+    Forfeited,
+    // Cancelled by the user during cool-off period
+    Cancelled,
+    // Waiting for arbiter's ruling, which settles the deposit
+    Disputed,
+    // Deposit of periodic habit returned for approved periods, the rest forfeited
+    PartiallyRefunded,
+}
+
+impl HabitStatus {
+    // Deposit has been paid out, no further transition is possible
+    pub fn is_settled(&self) -> bool {
+        matches!(
+            self,
+            Self::Refunded | Self::Forfeited | Self::Cancelled | Self::PartiallyRefunded
+        )
+    }
+}
+
+// Contract settings in force when the habit was created, the habit is settled under them
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HabitTerms {
+    dev_fee_bps: U64,
+    habit_acquisition_period: U64,
+    approval_grace_period: U64,
+    cancellation_period: U64,
+    cancellation_penalty_bps: U64,
+    acceptance_period: U64,
+    dispute_period: U64,
+}
+
+// Beneficiary's decision on the habit, rejection comes with a reason
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Vote {
+    beneficiary: AccountId,
     approved: bool,
+    reason: Option<String>,
+}
+
+// Deposit backing a new habit, NEAR unless token is set
+pub(crate) struct Stake {
+    user: AccountId,
+    amount: Balance,
+    token: Option<AccountId>,
+}
+
+impl Stake {
+    // NEAR attached by the caller
+    fn attached() -> Self {
+        Self {
+            user: env::predecessor_account_id(),
+            amount: env::attached_deposit(),
+            token: None,
+        }
+    }
+}
+
+// Beneficiary's reason for failing the habit
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Rejection {
+    reason: String,
+    rejected_at: U64,
 }
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct StickyHabitsContract {
     owner: AccountId,
+    proposed_owner: Option<AccountId>,
     balance: Balance,
-    dev_fee: u64,
+    dev_fee_bps: u64,
     id_counter: u64,
-    // percent
+    // basis points, 1/100 of percent
     habit_acquisition_period: u64,
     // Nanoseconds
     approval_grace_period: u64,
     // Nanoseconds
     habits: UnorderedMap<AccountId, Vector<Habit>>,
     beneficiaries: UnorderedMap<AccountId, Vector<AccountId>>,
+    // Habit id -> (user, index in the user's habits vector)
+    habit_index: UnorderedMap<String, (AccountId, u64)>,
+    // Nanoseconds
+    cancellation_period: u64,
+    // Basis points
+    cancellation_penalty_bps: u64,
+    // Beneficiary -> ids of habits waiting for acceptance of the role
+    invitations: UnorderedMap<AccountId, Vector<String>>,
+    // Nanoseconds
+    acceptance_period: u64,
+    // Nanoseconds
+    dispute_period: u64,
+    arbiters: UnorderedSet<AccountId>,
+    // yoctoNEAR attached to open a dispute
+    dispute_bond: Balance,
+    // Nanoseconds
+    ruling_period: u64,
+    // Habit id -> dispute over the habit
+    disputes: UnorderedMap<String, Dispute>,
+    // Habit id -> append-only history of evidence
+    evidence: UnorderedMap<String, Vector<Evidence>>,
+    // Bytes
+    max_description_length: u32,
+    // Bytes
+    max_evidence_length: u32,
+    // Account -> NEP-145 storage deposit and bytes used by the account's habits
+    storage_accounts: UnorderedMap<AccountId, StorageAccount>,
+    // User -> summaries of settled habits moved out of the user's habits
+    archived_habits: UnorderedMap<AccountId, Vector<HabitSummary>>,
+    // NEP-141 tokens accepted as stakes
+    whitelisted_tokens: UnorderedSet<AccountId>,
+    // Token -> amount locked in habits
+    token_balances: UnorderedMap<AccountId, Balance>,
+    // (account, token) -> settled amount the account can withdraw, token is None for NEAR
+    claimable: UnorderedMap<(AccountId, Option<AccountId>), Claim>,
+    // Sum of claimable NEAR
+    claimable_total: Balance,
+    // Developer fees and cancellation penalties in NEAR, claimable by the owner
+    dev_fees: Balance,
+    // Bytes of the claimable ledger, charged to the payers of its entries
+    claims_storage: StorageUsage,
+    // Sum of NEP-145 storage deposits
+    storage_deposits: Balance,
+    // Beneficiary -> creation sequence -> id of habit the beneficiary accepted
+    beneficiary_habits: UnorderedMap<AccountId, TreeMap<u64, String>>,
+    // Creation sequence -> (habit id, created at)
+    recent_habits: TreeMap<u64, (String, U64)>,
+    // Settlement sequence -> (habit id, settled at)
+    recent_settlements: TreeMap<u64, (String, U64)>,
+    settlement_counter: u64,
 }
 
 // Default, which automatically initializes the contract during first call
@@ -42,13 +257,40 @@ impl Default for StickyHabitsContract {
     fn default() -> Self {
         Self {
             owner: env::current_account_id(),
+            proposed_owner: None,
             balance: Balance::from(U128(0)),
-            dev_fee: 5,
+            dev_fee_bps: 500,
             id_counter: 0,
             habit_acquisition_period: 21 * 24 * 3600 * 1000000000_u64,
             approval_grace_period: 15 * 24 * 3600 * 1000000000_u64,
             habits: UnorderedMap::new(b"map-id-1".to_vec()),
             beneficiaries: UnorderedMap::new(b"map-id-2".to_vec()),
+            habit_index: UnorderedMap::new(b"map-id-3".to_vec()),
+            cancellation_period: 3600 * 1000000000_u64,
+            cancellation_penalty_bps: 0,
+            invitations: UnorderedMap::new(b"map-id-4".to_vec()),
+            acceptance_period: 3 * 24 * 3600 * 1000000000_u64,
+            dispute_period: 0,
+            arbiters: UnorderedSet::new(b"set-id-1".to_vec()),
+            dispute_bond: 1_000_000_000_000_000_000_000_000,
+            ruling_period: 7 * 24 * 3600 * 1000000000_u64,
+            disputes: UnorderedMap::new(b"map-id-5".to_vec()),
+            evidence: UnorderedMap::new(b"map-id-6".to_vec()),
+            max_description_length: 280,
+            max_evidence_length: 512,
+            storage_accounts: UnorderedMap::new(b"map-id-7".to_vec()),
+            archived_habits: UnorderedMap::new(b"map-id-8".to_vec()),
+            whitelisted_tokens: UnorderedSet::new(b"set-id-2".to_vec()),
+            token_balances: UnorderedMap::new(b"map-id-9".to_vec()),
+            claimable: UnorderedMap::new(b"map-id-10".to_vec()),
+            claimable_total: 0,
+            dev_fees: 0,
+            claims_storage: 0,
+            storage_deposits: 0,
+            beneficiary_habits: UnorderedMap::new(b"map-id-11".to_vec()),
+            recent_habits: TreeMap::new(b"tree-c-id".to_vec()),
+            recent_settlements: TreeMap::new(b"tree-s-id".to_vec()),
+            settlement_counter: 0,
         }
     }
 }
@@ -59,324 +301,1117 @@ impl StickyHabitsContract {
     #[private]
     pub fn init(
         owner: AccountId,
-        dev_fee: U64,
+        dev_fee_bps: U64,
         habit_acquisition_period: U64,
         approval_grace_period: U64,
     ) -> Self {
-        assert!(!env::state_exists(), "Already initialized");
+        if env::state_exists() {
+            StickyHabitsError::AlreadyInitialized.panic();
+        }
+        if u64::from(dev_fee_bps) > settlement::BPS_DENOMINATOR {
+            StickyHabitsError::InvalidDevFee(u64::from(dev_fee_bps)).panic();
+        }
+        // New state is created in the current layout
+        migration::write_state_version();
         Self {
             owner,
-            balance: Balance::from(U128(0)),
-            dev_fee: u64::from(dev_fee),
-            id_counter: 0,
+            dev_fee_bps: u64::from(dev_fee_bps),
             habit_acquisition_period: u64::from(habit_acquisition_period),
             approval_grace_period: u64::from(approval_grace_period),
-            habits: UnorderedMap::new(b"map-id-1".to_vec()),
-            beneficiaries: UnorderedMap::new(b"map-id-2".to_vec()),
+            ..Default::default()
         }
     }
 
     // Adds new habit for the user and links user to his beneficiary
     #[payable]
+    #[handle_result]
     pub fn add_habit(
         &mut self,
         description: String,
         deadline_extension: U64,
         beneficiary: AccountId,
-    ) {
-        log!("Adding new habit: {}", description);
-        // Get who is calling the method and how much $NEAR they attached
-        let user: AccountId = env::predecessor_account_id();
-        let user_str = user.as_str();
-        let beneficiary_str = beneficiary.as_str();
-        let deposit: Balance = env::attached_deposit();
-        let deadline =
-            env::block_timestamp() + self.habit_acquisition_period + u64::from(deadline_extension);
+        private: Option<bool>,
+    ) -> Result<(), StickyHabitsError> {
+        self.internal_add_habit(
+            Stake::attached(),
+            description,
+            deadline_extension,
+            vec![beneficiary],
+            1,
+            None,
+            private.unwrap_or(false),
+        )
+    }
 
-        // Check if user is different from beneficiary
-        assert_ne!(
-            user, beneficiary,
-            "User and Beneficiary should be different accounts"
-        );
+    // Adds new habit with a group of beneficiaries, approval_threshold of them have
+    // to approve the habit
+    #[payable]
+    #[handle_result]
+    pub fn add_group_habit(
+        &mut self,
+        description: String,
+        deadline_extension: U64,
+        beneficiaries: Vec<AccountId>,
+        approval_threshold: u16,
+        private: Option<bool>,
+    ) -> Result<(), StickyHabitsError> {
+        self.internal_add_habit(
+            Stake::attached(),
+            description,
+            deadline_extension,
+            beneficiaries,
+            approval_threshold,
+            None,
+            private.unwrap_or(false),
+        )
+    }
 
-        // Check if user has already any stored habits, if not create new vector
-        let mut existing_habits = match self.habits.get(&user) {
-            Some(v) => v,
-            None => Vector::new(("vector-h-id-".to_string() + user_str).as_bytes().to_vec()),
-        };
+    // Beneficiary accepts the role, stake is binding once all beneficiaries accept
+    #[handle_result]
+    pub fn accept_beneficiary_role(&mut self, id: String) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+        let current_time = env::block_timestamp();
 
-        let to_lock: Balance = if existing_habits.is_empty() {
-            // This is the user's first deposit, lets register it, which increases storage
-            assert!(
-                deposit > STORAGE_COST,
-                "Attach at least {} yoctoNEAR",
-                STORAGE_COST
-            );
+        let initial_storage = self.accounted_storage_usage();
 
-            // Subtract the storage cost to the amount to transfer
-            deposit - STORAGE_COST
-        } else {
-            deposit
-        };
+        let (user, index) = self.habit_location(&id)?;
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        if !habit.is_beneficiary(&account) {
+            return Err(StickyHabitsError::NotBeneficiary);
+        }
+        self.accept_beneficiary_action(
+            index,
+            user.clone(),
+            &account,
+            &mut existing_habits,
+            &mut habit,
+            current_time,
+        )?;
+        self.track_storage(&user, initial_storage, false)
+    }
+
+    // Any beneficiary declines the role, deposit is refunded to the user
+    #[handle_result]
+    pub fn decline_beneficiary_role(&mut self, id: String) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
 
-        // Get random seed from validator and append actual id_counter value
-        let r_seed = &mut env::random_seed();
-        let id_counter_b = &mut self.id_counter.to_le_bytes().to_vec();
-        let raw_id = {
-            r_seed.append(id_counter_b);
-            ().try_to_vec()
+        let (user, index) = self.habit_location(&id)?;
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        if !habit.is_beneficiary(&account) {
+            return Err(StickyHabitsError::NotBeneficiary);
         }
-        .unwrap();
+        let initial_storage = self.accounted_storage_usage();
+        self.decline_beneficiary_action(
+            index,
+            user.clone(),
+            &account,
+            &mut existing_habits,
+            &mut habit,
+        )?;
+        self.track_storage(&user, initial_storage, false)
+    }
 
-        // create a Sha256 object
-        let id = digest(raw_id.as_slice());
+    // Adds a single link to the video or image content or cloud storage folder,
+    // optionally with hex encoded SHA-256 digest of the content
+    #[payable]
+    #[handle_result]
+    pub fn update_evidence(
+        &mut self,
+        user: AccountId,
+        at_index: u16,
+        evidence: String,
+        digest: Option<String>,
+    ) -> Result<(), StickyHabitsError> {
+        self.internal_update_evidence(user, u64::from(at_index), evidence, digest)
+    }
 
-        existing_habits.push(&Habit {
-            id,
-            description: description.clone(),
-            deadline: U64(deadline),
-            deposit: U128(to_lock),
-            beneficiary: beneficiary.clone(),
-            evidence: "".to_string(),
-            approved: false,
-        });
+    // Same as update_evidence, habit is looked up by its id
+    #[payable]
+    #[handle_result]
+    pub fn update_evidence_by_id(
+        &mut self,
+        id: String,
+        evidence: String,
+        digest: Option<String>,
+    ) -> Result<(), StickyHabitsError> {
+        let (user, index) = self.habit_location(&id)?;
+        self.internal_update_evidence(user, index, evidence, digest)
+    }
 
-        self.habits.insert(&user, &existing_habits);
-        self.balance += to_lock;
+    // Beneficiary approves habit by setting "approved" flag to true
+    #[payable]
+    #[handle_result]
+    pub fn approve_habit(
+        &mut self,
+        user: AccountId,
+        at_index: u16,
+    ) -> Result<(), StickyHabitsError> {
+        self.internal_approve_habit(user, u64::from(at_index))
+    }
 
-        // Increment counter
-        self.id_counter += 1;
+    // Same as approve_habit, habit is looked up by its id
+    #[payable]
+    #[handle_result]
+    pub fn approve_habit_by_id(&mut self, id: String) -> Result<(), StickyHabitsError> {
+        let (user, index) = self.habit_location(&id)?;
+        self.internal_approve_habit(user, index)
+    }
 
-        log!(
-            "Deposit of {} has been made for habit {}",
-            to_lock,
-            description
-        );
+    // Beneficiary fails the habit before the approval window is over, deposit can be
+    // forfeited once the dispute period after rejection is over
+    #[handle_result]
+    pub fn reject_habit(
+        &mut self,
+        user: AccountId,
+        at_index: u16,
+        reason: String,
+    ) -> Result<(), StickyHabitsError> {
+        self.internal_reject_habit(user, u64::from(at_index), reason)
+    }
 
-        // Check if beneficiary has been assigned any users(habits) before, if not create new vector
-        let mut beneficiary_users = match self.beneficiaries.get(&beneficiary) {
-            Some(v) => v,
-            None => Vector::new(
-                ("vector-b-id-".to_string() + beneficiary_str)
-                    .as_bytes()
-                    .to_vec(),
-            ),
-        };
+    // Same as reject_habit, habit is looked up by its id
+    #[handle_result]
+    pub fn reject_habit_by_id(
+        &mut self,
+        id: String,
+        reason: String,
+    ) -> Result<(), StickyHabitsError> {
+        let (user, index) = self.habit_location(&id)?;
+        self.internal_reject_habit(user, index, reason)
+    }
 
-        // Link user with the beneficiary if not already present
-        match beneficiary_users.iter().find(|x| *x == user) {
-            Some(_item) => (),
-            None => {
-                // Add new or update beneficiary with this user
-                beneficiary_users.push(&user);
-                self.beneficiaries.insert(&beneficiary, &beneficiary_users);
-                log!("User {} assigned to the beneficiary {}", user, beneficiary);
-            }
-        }
+    #[payable]
+    #[handle_result]
+    pub fn unlock_deposit(
+        &mut self,
+        user: AccountId,
+        at_index: u16,
+    ) -> Result<(), StickyHabitsError> {
+        self.internal_unlock_deposit(user, u64::from(at_index))
     }
 
-    // Adds a single link to the video or image content or cloud storage folder
+    // Same as unlock_deposit, habit is looked up by its id
     #[payable]
-    pub fn update_evidence(&mut self, user: AccountId, at_index: u16, evidence: String) {
-        let index = u64::from(at_index);
+    #[handle_result]
+    pub fn unlock_deposit_by_id(&mut self, id: String) -> Result<(), StickyHabitsError> {
+        let (user, index) = self.habit_location(&id)?;
+        self.internal_unlock_deposit(user, index)
+    }
+
+    // User cancels the habit during cool-off period after creation, deposit is refunded
+    // minus cancellation penalty
+    #[handle_result]
+    pub fn cancel_habit(&mut self, id: String) -> Result<(), StickyHabitsError> {
         let account: AccountId = env::predecessor_account_id();
+        let current_time = env::block_timestamp();
 
-        log!("Updating habit evidence for user {}", user);
+        let (user, index) = self.habit_location(&id)?;
+        if user != account {
+            return Err(StickyHabitsError::NotHabitOwner);
+        }
 
-        assert!(!evidence.is_empty(), "Evidence cannot be empty");
-        assert_eq!(
-            user, account,
-            "User can update evidence only for her own habit"
-        );
+        let initial_storage = self.accounted_storage_usage();
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        self.cancel_action(
+            index,
+            user.clone(),
+            &mut existing_habits,
+            &mut habit,
+            current_time,
+        )?;
+        self.track_storage(&user, initial_storage, true)
+    }
 
-        let mut existing_habits = match self.habits.get(&user) {
-            Some(v) => v,
-            None => {
-                panic!("User {} has no habit yet", user);
-            }
-        };
+    // Returns the habit with given id, if any
+    pub fn get_habit(&self, id: String) -> Option<Habit> {
+        self.habit_index
+            .get(&id)
+            .and_then(|(user, index)| self.habits.get(&user)?.get(index))
+    }
 
-        match &mut existing_habits.get(index) {
-            Some(habit) => {
-                self.update_evidence_action(index, &mut existing_habits, habit, evidence);
-            }
-            None => panic!("Index {} is out of range", index),
+    // Derives unique habit id from validator random seed and actual id_counter value
+    fn next_habit_id(&mut self) -> Result<String, StickyHabitsError> {
+        let mut raw_id = env::random_seed();
+        raw_id.extend_from_slice(&self.id_counter.to_le_bytes());
+
+        // create a Sha256 object
+        let id = digest(raw_id.as_slice());
+        if self.habit_index.get(&id).is_some() {
+            return Err(StickyHabitsError::DuplicateHabitId(id));
         }
+
+        // Increment counter
+        self.id_counter += 1;
+
+        Ok(id)
     }
 
-    // Beneficiary approves habit by setting "approved" flag to true
-    #[payable]
-    pub fn approve_habit(&mut self, user: AccountId, at_index: u16) {
-        let index = u64::from(at_index);
-        let account: AccountId = env::predecessor_account_id();
-        let current_time = env::block_timestamp();
+    // Terms new habits are created under
+    fn current_terms(&self) -> HabitTerms {
+        HabitTerms {
+            dev_fee_bps: U64(self.dev_fee_bps),
+            habit_acquisition_period: U64(self.habit_acquisition_period),
+            approval_grace_period: U64(self.approval_grace_period),
+            cancellation_period: U64(self.cancellation_period),
+            cancellation_penalty_bps: U64(self.cancellation_penalty_bps),
+            acceptance_period: U64(self.acceptance_period),
+            dispute_period: U64(self.dispute_period),
+        }
+    }
 
-        log!("Approving habit for user {}", user);
+    // Resolves habit id to the owning user and position in user's habits vector
+    fn habit_location(&self, id: &str) -> Result<(AccountId, u64), StickyHabitsError> {
+        self.habit_index
+            .get(&id.to_string())
+            .ok_or_else(|| StickyHabitsError::HabitNotFound(id.to_string()))
+    }
 
-        let mut existing_habits = match self.habits.get(&user) {
-            Some(v) => v,
+    // Loads user's habits together with the habit at given index
+    fn habit_at(
+        &self,
+        user: &AccountId,
+        index: u64,
+    ) -> Result<(Vector<Habit>, Habit), StickyHabitsError> {
+        let existing_habits = self
+            .habits
+            .get(user)
+            .ok_or_else(|| StickyHabitsError::UserHasNoHabits(user.clone()))?;
+        let habit = existing_habits
+            .get(index)
+            .ok_or(StickyHabitsError::IndexOutOfRange(index))?;
+
+        Ok((existing_habits, habit))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn internal_add_habit(
+        &mut self,
+        stake: Stake,
+        description: String,
+        deadline_extension: U64,
+        beneficiaries: Vec<AccountId>,
+        approval_threshold: u16,
+        schedule: Option<Schedule>,
+        private: bool,
+    ) -> Result<(), StickyHabitsError> {
+        // Who stakes and how much $NEAR or tokens
+        let user: AccountId = stake.user;
+        let user_str = user.as_str();
+        let deposit: Balance = stake.amount;
+        let terms = self.current_terms();
+        let initial_storage = self.accounted_storage_usage();
+        // Periodic habit ends with its last period
+        let deadline = match &schedule {
+            Some(schedule) => {
+                env::block_timestamp() + schedule.periods() * schedule.cadence.period_length()
+            }
             None => {
-                panic!("User {} has no habit yet", user);
+                env::block_timestamp()
+                    + u64::from(terms.habit_acquisition_period)
+                    + u64::from(deadline_extension)
             }
         };
 
-        match &mut existing_habits.get(index) {
-            Some(habit) => {
-                assert_eq!(
-                    habit.beneficiary, account,
-                    "Only beneficiary can approve habit for user"
-                );
-                self.approve_action(index, &mut existing_habits, habit, current_time);
+        if description.len() > self.max_description_length as usize {
+            return Err(StickyHabitsError::DescriptionTooLong(
+                self.max_description_length,
+            ));
+        }
+        // Check if user is different from beneficiaries
+        if beneficiaries.contains(&user) {
+            return Err(StickyHabitsError::SameUserAndBeneficiary);
+        }
+        if beneficiaries.len() > MAX_BENEFICIARIES {
+            return Err(StickyHabitsError::TooManyBeneficiaries(MAX_BENEFICIARIES));
+        }
+        for (position, beneficiary) in beneficiaries.iter().enumerate() {
+            if beneficiaries[..position].contains(beneficiary) {
+                return Err(StickyHabitsError::DuplicateBeneficiary(beneficiary.clone()));
             }
+        }
+        if approval_threshold == 0 || usize::from(approval_threshold) > beneficiaries.len() {
+            return Err(StickyHabitsError::InvalidApprovalThreshold(
+                approval_threshold,
+            ));
+        }
+
+        // Check if user has already any stored habits, if not create new vector
+        let mut existing_habits = match self.habits.get(&user) {
+            Some(v) => v,
+            None => Vector::new(("vector-h-id-".to_string() + user_str).as_bytes().to_vec()),
+        };
+
+        // Whole deposit is locked, storage is paid from the user's storage balance
+        if deposit == 0 {
+            return Err(StickyHabitsError::InsufficientDeposit(1));
+        }
+        let to_lock: Balance = deposit;
+
+        let seq = self.id_counter;
+        let id = self.next_habit_id()?;
+
+        existing_habits.push(&Habit {
+            id: id.clone(),
+            description: description.clone(),
+            deadline: U64(deadline),
+            deposit: U128(to_lock),
+            beneficiaries: beneficiaries.clone(),
+            evidence: "".to_string(),
+            status: HabitStatus::Pending,
+            created_at: U64(env::block_timestamp()),
+            beneficiary_accepted: false,
+            terms,
+            rejection: None,
+            approval_threshold,
+            accepted_by: vec![],
+            votes: vec![],
+            schedule,
+            token: stake.token.clone(),
+            seq: U64(seq),
+            private,
+            settlement_seq: None,
+            disputed_for: U64(0),
+        });
 
-            None => panic!("Index {} is out of range", index),
+        self.habits.insert(&user, &existing_habits);
+        self.habit_index
+            .insert(&id, &(user.clone(), existing_habits.len() - 1));
+        self.record_creation(seq, &id);
+        self.lock_stake(&stake.token, to_lock);
+
+        // Beneficiaries are linked with the user only after accepting the role
+        for beneficiary in beneficiaries.iter() {
+            self.add_invitation(beneficiary, &id);
         }
+        self.track_storage(&user, initial_storage, true)?;
+
+        HabitEvent::HabitCreated(vec![HabitCreatedData {
+            user,
+            habit_id: id,
+            description,
+            deadline: U64(deadline),
+            deposit: U128(to_lock),
+            token: stake.token,
+            beneficiaries,
+            approval_threshold,
+        }])
+        .emit();
+
+        Ok(())
     }
 
-    #[payable]
-    pub fn unlock_deposit(&mut self, user: AccountId, at_index: u16) {
-        let index = u64::from(at_index);
+    fn internal_update_evidence(
+        &mut self,
+        user: AccountId,
+        index: u64,
+        evidence: String,
+        hash: Option<String>,
+    ) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+
+        self.validate_evidence(&evidence)?;
+        if user != account {
+            return Err(StickyHabitsError::NotHabitOwner);
+        }
+        let hash = crate::evidence::parse_digest(hash)?;
+
+        let initial_storage = self.accounted_storage_usage();
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        self.update_evidence_action(
+            index,
+            &user,
+            &mut existing_habits,
+            &mut habit,
+            evidence,
+            hash,
+        )?;
+        self.track_storage(&user, initial_storage, !habit.is_migrated())
+    }
+
+    fn internal_approve_habit(
+        &mut self,
+        user: AccountId,
+        index: u64,
+    ) -> Result<(), StickyHabitsError> {
         let account: AccountId = env::predecessor_account_id();
         let current_time = env::block_timestamp();
 
-        log!("Unlocking deposit for user {}", user);
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        if !habit.is_beneficiary(&account) {
+            return Err(StickyHabitsError::NotBeneficiary);
+        }
+        if !habit.beneficiary_accepted {
+            return Err(StickyHabitsError::BeneficiaryNotAccepted);
+        }
+        let initial_storage = self.accounted_storage_usage();
+        self.approve_action(
+            index,
+            &user,
+            &account,
+            &mut existing_habits,
+            &mut habit,
+            current_time,
+        )?;
+        self.track_storage(&user, initial_storage, false)
+    }
 
-        let mut existing_habits = match self.habits.get(&user) {
-            Some(v) => v,
-            None => {
-                panic!("User {} has no habit yet", user);
-            }
+    fn internal_reject_habit(
+        &mut self,
+        user: AccountId,
+        index: u64,
+        reason: String,
+    ) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+        let current_time = env::block_timestamp();
+
+        if reason.is_empty() {
+            return Err(StickyHabitsError::EmptyRejectionReason);
+        }
+
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        if !habit.is_beneficiary(&account) {
+            return Err(StickyHabitsError::NotBeneficiary);
+        }
+        if !habit.beneficiary_accepted {
+            return Err(StickyHabitsError::BeneficiaryNotAccepted);
+        }
+        let vote = Vote {
+            beneficiary: account,
+            approved: false,
+            reason: Some(reason),
         };
+        let initial_storage = self.accounted_storage_usage();
+        self.reject_action(
+            index,
+            &user,
+            &mut existing_habits,
+            &mut habit,
+            vote,
+            current_time,
+        )?;
+        self.track_storage(&user, initial_storage, false)
+    }
 
-        match &mut existing_habits.get(index) {
-            Some(habit) => {
-                assert!(
-                    account == habit.beneficiary || account == user,
-                    "Only user or beneficiary associated to the habit can unlock deposit"
-                );
-                self.unlock_deposit_action(index, user, &mut existing_habits, habit, current_time);
-            }
+    fn internal_unlock_deposit(
+        &mut self,
+        user: AccountId,
+        index: u64,
+    ) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+        let current_time = env::block_timestamp();
 
-            None => panic!("Index {} is out of range", index),
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        if !habit.is_beneficiary(&account) && account != user {
+            return Err(StickyHabitsError::NotUserOrBeneficiary);
         }
+        let initial_storage = self.accounted_storage_usage();
+        self.unlock_deposit_action(
+            index,
+            user.clone(),
+            &mut existing_habits,
+            &mut habit,
+            current_time,
+        )?;
+        self.track_storage(&user, initial_storage, false)
     }
 
     fn update_evidence_action(
-        &self,
+        &mut self,
         index: u64,
+        user: &AccountId,
         existing_habits: &mut Vector<Habit>,
         habit: &mut Habit,
         evidence: String,
-    ) {
+        hash: Option<String>,
+    ) -> Result<(), StickyHabitsError> {
+        if habit.status.is_settled() {
+            return Err(StickyHabitsError::AlreadySettled);
+        }
+        if !matches!(
+            habit.status,
+            HabitStatus::Pending | HabitStatus::EvidenceSubmitted
+        ) {
+            return Err(StickyHabitsError::InvalidStatus(habit.status));
+        }
+        // Periodic habit gets evidence per period by submit_check_in
+        if habit.schedule.is_some() {
+            return Err(StickyHabitsError::PeriodicHabit);
+        }
+
+        // Habit shows the latest evidence, earlier ones stay in the history
+        let entry = self.record_evidence(habit, evidence.clone(), hash);
         habit.evidence = evidence;
+        habit.status = HabitStatus::EvidenceSubmitted;
         let _updated = existing_habits.replace(index, habit);
+
+        HabitEvent::EvidenceUpdated(vec![EvidenceUpdatedData {
+            user: user.clone(),
+            habit_id: habit.id.clone(),
+            evidence: habit.evidence.clone(),
+            digest: entry.hash,
+            late: entry.late,
+        }])
+        .emit();
+
+        Ok(())
     }
 
     fn approve_action(
         &self,
         index: u64,
+        user: &AccountId,
+        voter: &AccountId,
         existing_habits: &mut Vector<Habit>,
         habit: &mut Habit,
         current_time: u64,
-    ) {
+    ) -> Result<(), StickyHabitsError> {
         let orig_deadline = u64::from(habit.deadline);
 
-        if orig_deadline < current_time && orig_deadline + self.approval_grace_period > current_time
-        {
-            habit.approved = true;
-            let _updated = existing_habits.replace(index, habit);
+        if orig_deadline >= current_time || self.unlockable_at(habit) <= current_time {
+            return Err(StickyHabitsError::OutsideApprovalWindow);
+        }
+        if habit.status.is_settled() {
+            return Err(StickyHabitsError::AlreadySettled);
+        }
+        if !matches!(
+            habit.status,
+            HabitStatus::Pending | HabitStatus::EvidenceSubmitted
+        ) {
+            return Err(StickyHabitsError::InvalidStatus(habit.status));
+        }
+        // Periodic habit is approved per period by approve_check_in
+        if habit.schedule.is_some() {
+            return Err(StickyHabitsError::PeriodicHabit);
+        }
+        if habit.has_voted(voter) {
+            return Err(StickyHabitsError::AlreadyVoted);
+        }
+
+        habit.votes.push(Vote {
+            beneficiary: voter.clone(),
+            approved: true,
+            reason: None,
+        });
+        // Habit is approved once enough beneficiaries voted for it
+        if habit.approvals() >= usize::from(habit.approval_threshold) {
+            habit.status = HabitStatus::Approved;
+        }
+        let _updated = existing_habits.replace(index, habit);
+
+        if habit.status == HabitStatus::Approved {
+            HabitEvent::HabitApproved(vec![HabitApprovedData {
+                user: user.clone(),
+                habit_id: habit.id.clone(),
+                beneficiary: voter.clone(),
+            }])
+            .emit();
+        } else {
+            HabitEvent::VoteRecorded(vec![VoteRecordedData {
+                user: user.clone(),
+                habit_id: habit.id.clone(),
+                beneficiary: voter.clone(),
+                approved: true,
+            }])
+            .emit();
         }
+
+        Ok(())
     }
 
-    fn unlock_deposit_action(
-        &mut self,
+    fn reject_action(
+        &self,
         index: u64,
-        user: AccountId,
+        user: &AccountId,
         existing_habits: &mut Vector<Habit>,
         habit: &mut Habit,
+        vote: Vote,
         current_time: u64,
-    ) {
-        let orig_deposit = u128::from(habit.deposit);
-        let orig_deadline = u64::from(habit.deadline);
-
-        if orig_deadline + self.approval_grace_period < current_time && habit.deposit > U128(0) {
-            match habit.approved {
-                // Return all deposit to the requesting user if conditions met
-                true => {
-                    Promise::new(user).transfer(orig_deposit);
-                }
-                // Split deposit between developer and beneficiary if conditions met, call by beneficiary
-                false => {
-                    let to_beneficiary = orig_deposit / (100 - self.dev_fee as u128);
-                    let to_developer = orig_deposit - to_beneficiary;
-                    Promise::new(habit.beneficiary.clone()).transfer(to_beneficiary);
-                    Promise::new(self.owner.clone()).transfer(to_developer);
-                }
+    ) -> Result<(), StickyHabitsError> {
+        if habit.status.is_settled() {
+            return Err(StickyHabitsError::AlreadySettled);
+        }
+        if !matches!(
+            habit.status,
+            HabitStatus::Pending | HabitStatus::EvidenceSubmitted
+        ) {
+            return Err(StickyHabitsError::InvalidStatus(habit.status));
+        }
+        // Periodic habit fails only for the periods left unapproved
+        if habit.schedule.is_some() {
+            return Err(StickyHabitsError::PeriodicHabit);
+        }
+        if self.unlockable_at(habit) <= current_time {
+            return Err(StickyHabitsError::RejectionPeriodOver);
+        }
+        if habit.has_voted(&vote.beneficiary) {
+            return Err(StickyHabitsError::AlreadyVoted);
+        }
+
+        let beneficiary = vote.beneficiary.clone();
+        let reason = vote.reason.clone().unwrap_or_default();
+        habit.votes.push(vote);
+        // Habit is rejected once the approval threshold cannot be reached anymore
+        let max_rejections = habit.beneficiaries.len() - usize::from(habit.approval_threshold);
+        if habit.rejections() > max_rejections {
+            habit.status = HabitStatus::Rejected;
+            habit.rejection = Some(Rejection {
+                reason: reason.clone(),
+                rejected_at: U64(current_time),
+            });
+        }
+        let _updated = existing_habits.replace(index, habit);
+
+        if habit.status == HabitStatus::Rejected {
+            HabitEvent::HabitRejected(vec![HabitRejectedData {
+                user: user.clone(),
+                habit_id: habit.id.clone(),
+                beneficiary,
+                reason,
+                unlockable_at: U64(self.unlockable_at(habit)),
+            }])
+            .emit();
+        } else {
+            HabitEvent::VoteRecorded(vec![VoteRecordedData {
+                user: user.clone(),
+                habit_id: habit.id.clone(),
+                beneficiary,
+                approved: false,
+            }])
+            .emit();
+        }
+
+        Ok(())
+    }
+
+    fn accept_beneficiary_action(
+        &mut self,
+        index: u64,
+        user: AccountId,
+        beneficiary: &AccountId,
+        existing_habits: &mut Vector<Habit>,
+        habit: &mut Habit,
+        current_time: u64,
+    ) -> Result<(), StickyHabitsError> {
+        if habit.status.is_settled() {
+            return Err(StickyHabitsError::AlreadySettled);
+        }
+        if habit.accepted_by.contains(beneficiary) {
+            return Err(StickyHabitsError::BeneficiaryAlreadyAccepted);
+        }
+        // Until accepted, habit is unlockable once the acceptance period is over
+        if self.unlockable_at(habit) < current_time {
+            return Err(StickyHabitsError::AcceptancePeriodOver);
+        }
+
+        habit.accepted_by.push(beneficiary.clone());
+        habit.beneficiary_accepted = habit.accepted_by.len() == habit.beneficiaries.len();
+        let _updated = existing_habits.replace(index, habit);
+        self.remove_invitation(beneficiary, &habit.id);
+        self.link_beneficiary(beneficiary, &user);
+        self.add_beneficiary_habit(beneficiary, habit);
+
+        HabitEvent::BeneficiaryAccepted(vec![BeneficiaryAcceptedData {
+            user,
+            habit_id: habit.id.clone(),
+            beneficiary: beneficiary.clone(),
+        }])
+        .emit();
+
+        Ok(())
+    }
+
+    fn decline_beneficiary_action(
+        &mut self,
+        index: u64,
+        user: AccountId,
+        beneficiary: &AccountId,
+        existing_habits: &mut Vector<Habit>,
+        habit: &mut Habit,
+    ) -> Result<(), StickyHabitsError> {
+        let orig_deposit = u128::from(habit.deposit);
+
+        if habit.status.is_settled() {
+            return Err(StickyHabitsError::AlreadySettled);
+        }
+        if habit.accepted_by.contains(beneficiary) {
+            return Err(StickyHabitsError::BeneficiaryAlreadyAccepted);
+        }
+
+        self.credit(&user, &habit.token, orig_deposit, &user);
+        self.unlock_stake(&habit.token, orig_deposit);
+        habit.deposit = U128(0);
+        habit.status = HabitStatus::Refunded;
+        self.record_settlement(habit);
+        let _updated = existing_habits.replace(index, habit);
+        self.remove_invitations(habit);
+
+        HabitEvent::BeneficiaryDeclined(vec![BeneficiaryDeclinedData {
+            user,
+            habit_id: habit.id.clone(),
+            beneficiary: beneficiary.clone(),
+            refund: U128(orig_deposit),
+        }])
+        .emit();
+
+        Ok(())
+    }
+
+    fn cancel_action(
+        &mut self,
+        index: u64,
+        user: AccountId,
+        existing_habits: &mut Vector<Habit>,
+        habit: &mut Habit,
+        current_time: u64,
+    ) -> Result<(), StickyHabitsError> {
+        let orig_deposit = u128::from(habit.deposit);
+
+        match habit.status {
+            HabitStatus::Pending => (),
+            HabitStatus::EvidenceSubmitted => {
+                return Err(StickyHabitsError::EvidenceAlreadySubmitted)
             }
-            self.balance -= orig_deposit;
-            habit.deposit = U128(0);
-            let _updated = existing_habits.replace(index, habit);
+            status if status.is_settled() => return Err(StickyHabitsError::AlreadySettled),
+            status => return Err(StickyHabitsError::InvalidStatus(status)),
+        }
+        if u64::from(habit.created_at) + u64::from(habit.terms.cancellation_period) < current_time {
+            return Err(StickyHabitsError::CancellationPeriodOver);
+        }
+
+        let (to_user, penalty) = settlement::split_deposit(
+            orig_deposit,
+            u64::from(habit.terms.cancellation_penalty_bps),
+        );
+        self.credit(&user, &habit.token, to_user, &user);
+        self.credit_dev_fee(&habit.token, penalty, &user);
+        self.unlock_stake(&habit.token, orig_deposit);
+        habit.deposit = U128(0);
+        habit.status = HabitStatus::Cancelled;
+        self.record_settlement(habit);
+        let _updated = existing_habits.replace(index, habit);
+        if !habit.beneficiary_accepted {
+            self.remove_invitations(habit);
         }
+
+        HabitEvent::HabitCancelled(vec![HabitCancelledData {
+            user,
+            habit_id: habit.id.clone(),
+            refund: U128(to_user),
+            penalty: U128(penalty),
+        }])
+        .emit();
+
+        Ok(())
     }
 
-    // Returns an array of habits for the user with from and limit parameters.
-    pub fn get_habits_user(
-        &self,
+    fn unlock_deposit_action(
+        &mut self,
+        index: u64,
         user: AccountId,
-        from_index: Option<u16>,
-        limit_to: Option<u16>,
-    ) -> Vec<Habit> {
-        let from = usize::from(from_index.unwrap_or(0u16));
-        let limit = usize::from(limit_to.unwrap_or(1u16));
+        existing_habits: &mut Vector<Habit>,
+        habit: &mut Habit,
+        current_time: u64,
+    ) -> Result<(), StickyHabitsError> {
+        if habit.status.is_settled() {
+            return Err(StickyHabitsError::AlreadySettled);
+        }
+        // Disputed habit is settled by the arbiter's ruling
+        if habit.status == HabitStatus::Disputed {
+            return Err(StickyHabitsError::InvalidStatus(habit.status));
+        }
+        if self.unlockable_at(habit) >= current_time {
+            return Err(StickyHabitsError::TooEarlyToUnlock);
+        }
+
+        self.settle_action(index, user, existing_habits, habit);
+        Ok(())
+    }
+
+    // Pays out the deposit according to habit status and marks the habit settled
+    fn settle_action(
+        &mut self,
+        index: u64,
+        user: AccountId,
+        existing_habits: &mut Vector<Habit>,
+        habit: &mut Habit,
+    ) {
+        let orig_deposit = u128::from(habit.deposit);
+
+        // Refunded part goes to the user, the rest is split between beneficiaries
+        // and developer
+        let settlement = self.settlement(habit);
+        let beneficiary_shares =
+            settlement::split_evenly(settlement.to_beneficiary.0, habit.beneficiaries.len());
+        let mut credits = vec![(user.clone(), settlement.to_user.0)];
+        credits.extend(
+            habit
+                .beneficiaries
+                .iter()
+                .cloned()
+                .zip(beneficiary_shares.iter().copied()),
+        );
+        for (receiver, amount) in credits {
+            self.credit(&receiver, &habit.token, amount, &user);
+        }
+        self.credit_dev_fee(&habit.token, settlement.to_developer.0, &user);
+        self.unlock_stake(&habit.token, orig_deposit);
+        habit.deposit = U128(0);
+        habit.status = match self.refund_ratio(habit) {
+            (0, _) => HabitStatus::Forfeited,
+            (approved, periods) if approved == periods => HabitStatus::Refunded,
+            _ => HabitStatus::PartiallyRefunded,
+        };
+        self.record_settlement(habit);
+        let _updated = existing_habits.replace(index, habit);
+        if !habit.beneficiary_accepted {
+            self.remove_invitations(habit);
+        }
+
+        if habit.status != HabitStatus::Forfeited {
+            HabitEvent::DepositUnlocked(vec![DepositUnlockedData {
+                user: user.clone(),
+                habit_id: habit.id.clone(),
+                amount: settlement.to_user,
+            }])
+            .emit();
+        }
+        if habit.status != HabitStatus::Refunded {
+            // One entry per beneficiary, developer fee is reported with the first one
+            HabitEvent::DepositForfeited(
+                habit
+                    .beneficiaries
+                    .iter()
+                    .zip(beneficiary_shares)
+                    .enumerate()
+                    .map(|(position, (beneficiary, share))| DepositForfeitedData {
+                        user: user.clone(),
+                        habit_id: habit.id.clone(),
+                        beneficiary: beneficiary.clone(),
+                        to_beneficiary: U128(share),
+                        to_developer: if position == 0 {
+                            settlement.to_developer
+                        } else {
+                            U128(0)
+                        },
+                    })
+                    .collect(),
+            )
+            .emit();
+        }
+    }
+
+    // Deposit can be unlocked once the approval grace period after deadline is over,
+    // the dispute period after rejection is over, or when beneficiary has not accepted
+    // the role in time. Time the habit spent under an expired dispute is added on top.
+    fn unlockable_at(&self, habit: &Habit) -> u64 {
+        let unlockable_at = if let Some(rejection) = &habit.rejection {
+            u64::from(rejection.rejected_at) + u64::from(habit.terms.dispute_period)
+        } else if habit.beneficiary_accepted {
+            u64::from(habit.deadline) + u64::from(habit.terms.approval_grace_period)
+        } else {
+            u64::from(habit.created_at) + u64::from(habit.terms.acceptance_period)
+        };
+        unlockable_at + u64::from(habit.disputed_for)
+    }
 
-        let existing_habits = match self.habits.get(&user) {
+    // Adds user to beneficiary's users if not already present
+    fn link_beneficiary(&mut self, beneficiary: &AccountId, user: &AccountId) {
+        // Check if beneficiary has been assigned any users(habits) before, if not create new vector
+        let mut beneficiary_users = match self.beneficiaries.get(beneficiary) {
             Some(v) => v,
-            None => Vector::new(b"vector-id-1".to_vec()),
+            None => Vector::new(
+                ("vector-b-id-".to_string() + beneficiary.as_str())
+                    .as_bytes()
+                    .to_vec(),
+            ),
         };
 
-        existing_habits.iter().skip(from).take(limit).collect()
+        // Link user with the beneficiary if not already present
+        match beneficiary_users.iter().find(|x| x == user) {
+            Some(_item) => (),
+            None => {
+                // Add new or update beneficiary with this user
+                beneficiary_users.push(user);
+                self.beneficiaries.insert(beneficiary, &beneficiary_users);
+            }
+        }
     }
 
-    // Returns a map of habits of beneficiary's friends with from and limit parameters.
-    pub fn get_habits_beneficiary(
+    // Lists the habit among the beneficiary's habits
+    pub(crate) fn add_beneficiary_habit(&mut self, beneficiary: &AccountId, habit: &Habit) {
+        let mut beneficiary_habits = match self.beneficiary_habits.get(beneficiary) {
+            Some(v) => v,
+            None => TreeMap::new(
+                ("tree-b-id-".to_string() + beneficiary.as_str())
+                    .as_bytes()
+                    .to_vec(),
+            ),
+        };
+        beneficiary_habits.insert(&u64::from(habit.seq), &habit.id);
+        self.beneficiary_habits
+            .insert(beneficiary, &beneficiary_habits);
+    }
+
+    // Removes the habit from habits of all its beneficiaries
+    pub(crate) fn remove_beneficiary_habits(&mut self, habit: &Habit) {
+        for beneficiary in habit.beneficiaries.iter() {
+            if let Some(mut beneficiary_habits) = self.beneficiary_habits.get(beneficiary) {
+                beneficiary_habits.remove(&u64::from(habit.seq));
+                if beneficiary_habits.is_empty() {
+                    self.beneficiary_habits.remove(beneficiary);
+                } else {
+                    self.beneficiary_habits
+                        .insert(beneficiary, &beneficiary_habits);
+                }
+            }
+        }
+    }
+
+    // Adds habit id to beneficiary's pending invitations
+    fn add_invitation(&mut self, beneficiary: &AccountId, id: &String) {
+        let mut invitations = match self.invitations.get(beneficiary) {
+            Some(v) => v,
+            None => Vector::new(
+                ("vector-i-id-".to_string() + beneficiary.as_str())
+                    .as_bytes()
+                    .to_vec(),
+            ),
+        };
+        invitations.push(id);
+        self.invitations.insert(beneficiary, &invitations);
+    }
+
+    // Removes habit from pending invitations of all its beneficiaries
+    fn remove_invitations(&mut self, habit: &Habit) {
+        for beneficiary in habit.beneficiaries.iter() {
+            self.remove_invitation(beneficiary, &habit.id);
+        }
+    }
+
+    // Removes habit id from beneficiary's pending invitations
+    fn remove_invitation(&mut self, beneficiary: &AccountId, id: &str) {
+        if let Some(mut invitations) = self.invitations.get(beneficiary) {
+            if let Some(position) = invitations.iter().position(|x| x == id) {
+                invitations.swap_remove(position as u64);
+                self.invitations.insert(beneficiary, &invitations);
+            }
+        }
+    }
+
+    // Part of the deposit refunded on unlock as (numerator, denominator). Approved habit
+    // or habit the beneficiaries never accepted is refunded fully, periodic habit
+    // proportionally to the periods approved.
+    fn refund_ratio(&self, habit: &Habit) -> (u64, u64) {
+        if habit.status == HabitStatus::Approved || !habit.beneficiary_accepted {
+            return (1, 1);
+        }
+        match &habit.schedule {
+            Some(schedule) if habit.status != HabitStatus::Rejected => (
+                schedule.approved_periods(habit.approval_threshold),
+                schedule.periods(),
+            ),
+            _ => (0, 1),
+        }
+    }
+
+    fn settlement(&self, habit: &Habit) -> Settlement {
+        let (numerator, denominator) = self.refund_ratio(habit);
+        Settlement::new(
+            u128::from(habit.deposit),
+            settlement::pro_rata(u128::from(habit.deposit), numerator, denominator),
+            u64::from(habit.terms.dev_fee_bps),
+            self.unlockable_at(habit),
+        )
+    }
+
+    // Returns how the habit deposit would be distributed by unlock_deposit
+    #[handle_result]
+    pub fn preview_settlement(
         &self,
-        beneficiary: AccountId,
-        from_index: Option<u16>,
+        user: AccountId,
+        habit_id: String,
+    ) -> Result<Settlement, StickyHabitsError> {
+        let (owner, index) = self.habit_location(&habit_id)?;
+        if owner != user {
+            return Err(StickyHabitsError::HabitNotFound(habit_id));
+        }
+
+        let (_, habit) = self.habit_at(&user, index)?;
+        Ok(self.settlement(&habit))
+    }
+
+    // Returns page of user's habits in creation order following the cursor
+    pub fn get_habits_user(
+        &self,
+        user: AccountId,
+        cursor: Option<U64>,
         limit_to: Option<u16>,
-    ) -> HashMap<AccountId, Vec<Habit>> {
-        let from = usize::from(from_index.unwrap_or(0u16));
-        let limit = usize::from(limit_to.unwrap_or(1u16));
+    ) -> Page<Habit> {
+        self.user_habits_page(&user, cursor, limit_to, |_| true)
+    }
 
-        let mut friends_habits: HashMap<AccountId, Vec<Habit>> = HashMap::new();
+    // Returns page of user's habits in given status in creation order following the cursor
+    pub fn get_habits_user_by_status(
+        &self,
+        user: AccountId,
+        status: HabitStatus,
+        cursor: Option<U64>,
+        limit_to: Option<u16>,
+    ) -> Page<Habit> {
+        self.user_habits_page(&user, cursor, limit_to, |h| h.status == status)
+    }
 
-        // Get users associated with beneficiary
-        let beneficiary_users = match self.beneficiaries.get(&beneficiary) {
+    // Returns page of habits accepted by the beneficiary in creation order following the cursor
+    pub fn get_habits_beneficiary(
+        &self,
+        beneficiary: AccountId,
+        cursor: Option<U64>,
+        limit_to: Option<u16>,
+    ) -> Page<UserHabit> {
+        let beneficiary_habits = match self.beneficiary_habits.get(&beneficiary) {
             Some(v) => v,
-            None => Vector::new(b"vector-id-2".to_vec()),
+            None => return pagination::paginate(std::iter::empty(), limit_to),
         };
+        let from = cursor.map_or(Bound::Included(0), |c| Bound::Excluded(u64::from(c)));
+
+        pagination::paginate(
+            beneficiary_habits
+                .range((from, Bound::Unbounded))
+                .map(|(seq, id)| {
+                    let user_habit = self.habit_index.get(&id).and_then(|(user, index)| {
+                        let habit = self.habits.get(&user)?.get(index)?;
+                        Some(UserHabit { user, habit })
+                    });
+                    (seq, user_habit)
+                }),
+            limit_to,
+        )
+    }
 
-        // Get habits from all associated users and filter them to those belonging to beneficiary
-        for user in beneficiary_users.iter() {
-            let user_habits = match self.habits.get(&user) {
-                Some(v) => v,
-                None => Vector::new(b"vector-id-1".to_vec()),
-            };
-            let user_habits_filtered: Vec<Habit> = user_habits
-                .iter()
-                .skip(from)
-                .take(limit)
-                .filter(|b| b.beneficiary == beneficiary)
-                .collect();
-            friends_habits.insert(user, user_habits_filtered);
+    // Returns a map of habits waiting for beneficiary to accept the role
+    pub fn get_invitations(&self, beneficiary: AccountId) -> HashMap<AccountId, Vec<Habit>> {
+        let mut invited_habits: HashMap<AccountId, Vec<Habit>> = HashMap::new();
+
+        if let Some(invitations) = self.invitations.get(&beneficiary) {
+            for id in invitations.iter() {
+                if let Some((user, index)) = self.habit_index.get(&id) {
+                    if let Some(habit) = self.habits.get(&user).and_then(|v| v.get(index)) {
+                        invited_habits.entry(user).or_default().push(habit);
+                    }
+                }
+            }
         }
 
-        friends_habits
+        invited_habits
     }
 
-    // Returns actual contract balance
-    pub fn get_balance(&self) -> U64 {
-        assert!(env::state_exists(), "Not initialized yet");
-        U64(self.balance as u64)
+    // Returns NEAR locked in habits and open disputes
+    #[handle_result]
+    pub fn get_balance(&self) -> Result<U128, StickyHabitsError> {
+        if !env::state_exists() {
+            return Err(StickyHabitsError::NotInitialized);
+        }
+        Ok(U128(self.balance))
     }
 }
 
@@ -388,17 +1423,70 @@ mod tests {
     use near_sdk::Balance;
     use std::str::FromStr;
 
-    const OWNER: &str = "joe";
-    const NEAR: u128 = 1000000000000000000000000;
+    // Shared by the tests of all modules
+    pub(crate) const OWNER: &str = "joe";
+    pub(crate) const NEAR: u128 = 1000000000000000000000000;
+    pub(crate) const DAY: u64 = 24 * 3600 * 1000000000;
+    pub(crate) const START: u64 = 1662312790000000000;
 
-    // Auxiliary fn: create a mock context
-    fn set_context(predecessor: &str, amount: Balance, timestamp: u64) {
+    // Auxiliary fn: create a mock context builder
+    pub(crate) fn context(predecessor: &str, amount: Balance, timestamp: u64) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
         builder.predecessor_account_id(predecessor.parse().unwrap());
         builder.attached_deposit(amount);
         builder.block_timestamp(timestamp);
+        builder
+    }
+
+    // Auxiliary fn: create a mock context
+    pub(crate) fn set_context(predecessor: &str, amount: Balance, timestamp: u64) {
+        testing_env!(context(predecessor, amount, timestamp).build());
+    }
+
+    // Auxiliary fn: account deposits storage for its habits
+    pub(crate) fn register(contract: &mut StickyHabitsContract, account: &str) {
+        set_context(account, NEAR, START);
+        contract.storage_deposit(None, None).unwrap();
+    }
+
+    // Auxiliary fn: registered user adds habit with the beneficiary at the start, returns its id
+    pub(crate) fn add_habit(
+        contract: &mut StickyHabitsContract,
+        user: &str,
+        description: &str,
+        beneficiary: &str,
+        deposit: Balance,
+    ) -> String {
+        set_context(user, deposit, START);
+        contract
+            .add_habit(
+                description.to_string(),
+                U64(0),
+                AccountId::from_str(beneficiary).unwrap(),
+                None,
+            )
+            .unwrap();
+        let habits = contract.habits.get(&user.parse().unwrap()).unwrap();
+        habits.get(habits.len() - 1).unwrap().id
+    }
 
-        testing_env!(builder.build());
+    // Auxiliary fn: remaining beneficiaries accept the role for user's habit at index
+    pub(crate) fn accept_role(
+        contract: &mut StickyHabitsContract,
+        user: &str,
+        index: u16,
+        timestamp: u64,
+    ) {
+        let habit = &contract
+            .get_habits_user(AccountId::from_str(user).unwrap(), None, None)
+            .items[usize::from(index)];
+        for beneficiary in habit.beneficiaries.iter() {
+            if habit.accepted_by.contains(beneficiary) {
+                continue;
+            }
+            set_context(beneficiary.as_str(), 0, timestamp);
+            contract.accept_beneficiary_role(habit.id.clone()).unwrap();
+        }
     }
 
     #[test]
@@ -406,8 +1494,8 @@ mod tests {
         let contract = StickyHabitsContract::init(
             OWNER.parse().unwrap(),
             U64(7),
-            U64(1 * 24 * 3600 * 1000000000),
-            U64(1 * 24 * 3600 * 1000000000),
+            U64(24 * 3600 * 1000000000),
+            U64(24 * 3600 * 1000000000),
         );
         assert_eq!(contract.owner, OWNER.parse().unwrap())
     }
@@ -416,36 +1504,47 @@ mod tests {
     fn adds_habit() {
         let mut contract = StickyHabitsContract::default();
 
+        register(&mut contract, "roman");
         set_context("roman", 10 * NEAR, 1664172263000000000);
-        contract.add_habit(
-            "Clean my keyboard once a week".to_string(),
-            U64(0),
-            AccountId::from_str("adam").unwrap(),
-        );
+        contract
+            .add_habit(
+                "Clean my keyboard once a week".to_string(),
+                U64(0),
+                AccountId::from_str("adam").unwrap(),
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 0, 1664172263000000000);
 
+        register(&mut contract, "adam");
         set_context("adam", 10 * NEAR, 1664172263000000000);
-        contract.add_habit(
-            "Help father with car repair".to_string(),
-            U64(0),
-            AccountId::from_str("roman").unwrap(),
-        );
+        contract
+            .add_habit(
+                "Help father with car repair".to_string(),
+                U64(0),
+                AccountId::from_str("roman").unwrap(),
+                None,
+            )
+            .unwrap();
 
-        let posted_habit =
-            &contract.get_habits_user(AccountId::from_str("roman").unwrap(), None, None)[0];
+        let posted_habit = &contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0];
 
         let friends_habits =
             contract.get_habits_beneficiary(AccountId::from_str("adam").unwrap(), None, None);
-        let romans_habits = friends_habits
-            .get(&AccountId::from_str("roman").unwrap())
-            .unwrap();
 
         assert_eq!(
             posted_habit.description,
             "Clean my keyboard once a week".to_string()
         );
-        assert_eq!(u128::from(posted_habit.deposit), 10 * NEAR - STORAGE_COST);
+        assert_eq!(u128::from(posted_habit.deposit), 10 * NEAR);
         assert_eq!(
-            romans_habits[0].description,
+            friends_habits.items[0].user,
+            AccountId::from_str("roman").unwrap()
+        );
+        assert_eq!(
+            friends_habits.items[0].habit.description,
             "Clean my keyboard once a week".to_string()
         );
     }
@@ -454,28 +1553,39 @@ mod tests {
     fn updates_evidence() {
         let mut contract = StickyHabitsContract::default();
 
+        register(&mut contract, "roman");
         set_context("roman", 10 * NEAR, 1664172263000000000);
-        contract.add_habit(
-            "Clean my keyboard once a week".to_string(),
-            U64(0),
-            AccountId::from_str("adam").unwrap(),
-        );
+        contract
+            .add_habit(
+                "Clean my keyboard once a week".to_string(),
+                U64(0),
+                AccountId::from_str("adam").unwrap(),
+                None,
+            )
+            .unwrap();
 
         set_context("roman", 10 * NEAR, 1664172263000000000);
-        contract.add_habit(
-            "Wake up every day at the same time".to_string(),
-            U64(0),
-            AccountId::from_str("maria").unwrap(),
-        );
+        contract
+            .add_habit(
+                "Wake up every day at the same time".to_string(),
+                U64(0),
+                AccountId::from_str("maria").unwrap(),
+                None,
+            )
+            .unwrap();
 
-        contract.update_evidence(
-            AccountId::from_str("roman").unwrap(),
-            1,
-            "https://www.icloud.com/myfile.mov".to_string(),
-        );
+        contract
+            .update_evidence(
+                AccountId::from_str("roman").unwrap(),
+                1,
+                "https://www.icloud.com/myfile.mov".to_string(),
+                None,
+            )
+            .unwrap();
 
-        let updated_habit =
-            &contract.get_habits_user(AccountId::from_str("roman").unwrap(), None, Some(2))[1];
+        let updated_habit = &contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, Some(2))
+            .items[1];
         assert_eq!(
             updated_habit.evidence,
             "https://www.icloud.com/myfile.mov".to_string()
@@ -486,43 +1596,121 @@ mod tests {
     fn iterates_habits() {
         let mut contract = StickyHabitsContract::default();
 
+        register(&mut contract, "roman");
         set_context("roman", 20 * NEAR, 1664172263000000000);
-        contract.add_habit(
-            "Clean my keyboard once a week".to_string(),
-            U64(0),
-            AccountId::from_str("josef").unwrap(),
-        );
+        contract
+            .add_habit(
+                "Clean my keyboard once a week".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
 
         set_context("roman", 20 * NEAR, 1664172263000000000);
-        contract.add_habit(
-            "Eat two tomatoes every day".to_string(),
-            U64(0),
-            AccountId::from_str("b3b3bccd6ceee15c1610421568a03b5dcff6d1672374840d4da2c38c15ba1235")
+        contract
+            .add_habit(
+                "Eat two tomatoes every day".to_string(),
+                U64(0),
+                AccountId::from_str(
+                    "b3b3bccd6ceee15c1610421568a03b5dcff6d1672374840d4da2c38c15ba1235",
+                )
                 .unwrap(),
-        );
+                None,
+            )
+            .unwrap();
 
         set_context("roman", 20 * NEAR, 1664172263000000000);
-        contract.add_habit(
-            "Exercise without smartphone".to_string(),
-            U64(60000000000),
-            AccountId::from_str("alice").unwrap(),
-        );
+        contract
+            .add_habit(
+                "Exercise without smartphone".to_string(),
+                U64(60000000000),
+                AccountId::from_str("alice").unwrap(),
+                None,
+            )
+            .unwrap();
 
-        let habits =
-            &contract.get_habits_user(AccountId::from_str("roman").unwrap(), None, Some(3));
+        let habits = &contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, Some(3))
+            .items;
         assert_eq!(habits.len(), 3);
 
-        let last_habit =
-            &contract.get_habits_user(AccountId::from_str("roman").unwrap(), Some(1), Some(2))[1];
+        // Next page starts after the cursor
+        let page = contract.get_habits_user(AccountId::from_str("roman").unwrap(), None, Some(2));
+        assert_eq!(page.next_cursor, Some(habits[1].seq));
+        let page = contract.get_habits_user(
+            AccountId::from_str("roman").unwrap(),
+            page.next_cursor,
+            Some(2),
+        );
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.next_cursor, None);
+        let last_habit = &page.items[0];
         assert_eq!(
             u64::from(last_habit.deadline),
             1664172263000000000 + contract.habit_acquisition_period + 60000000000
         );
         assert_eq!(
-            last_habit.beneficiary,
-            AccountId::from_str("alice").unwrap()
+            last_habit.beneficiaries,
+            vec![AccountId::from_str("alice").unwrap()]
+        );
+        assert_eq!(last_habit.status, HabitStatus::Pending);
+    }
+
+    #[test]
+    fn pages_beneficiary_habits() {
+        let mut contract = StickyHabitsContract::default();
+
+        // Josef is beneficiary of every other habit of roman and adam
+        for user in ["roman", "adam"] {
+            register(&mut contract, user);
+            for (i, beneficiary) in ["josef", "alice", "josef", "alice", "josef"]
+                .iter()
+                .enumerate()
+            {
+                set_context(user, NEAR, 1664172263000000000);
+                contract
+                    .add_habit(
+                        format!("Habit {} of {}", i, user),
+                        U64(0),
+                        AccountId::from_str(beneficiary).unwrap(),
+                        None,
+                    )
+                    .unwrap();
+                accept_role(&mut contract, user, i as u16, 1664172263000000000);
+            }
+        }
+
+        let josef = AccountId::from_str("josef").unwrap();
+        let mut descriptions = vec![];
+        let mut cursor = None;
+        loop {
+            let page = contract.get_habits_beneficiary(josef.clone(), cursor, Some(2));
+            assert!(!page.items.is_empty());
+            descriptions.extend(page.items.into_iter().map(|h| h.habit.description));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(
+            descriptions,
+            vec![
+                "Habit 0 of roman",
+                "Habit 2 of roman",
+                "Habit 4 of roman",
+                "Habit 0 of adam",
+                "Habit 2 of adam",
+                "Habit 4 of adam"
+            ]
+        );
+        assert_eq!(
+            contract
+                .get_habits_beneficiary(josef, None, None)
+                .items
+                .len(),
+            6
         );
-        assert!(!last_habit.approved);
     }
 
     #[test]
@@ -530,35 +1718,1168 @@ mod tests {
         // Add habit
         let mut contract = StickyHabitsContract::default();
 
+        register(&mut contract, "roman");
         set_context("roman", 20 * NEAR, 1662312790000000000);
-        contract.add_habit(
-            "Do 15 push-ups everyday".to_string(),
-            U64(0),
-            AccountId::from_str("josef").unwrap(),
-        );
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
 
         // Failed unlock from user side - on habit not approved
         set_context("roman", 0, 1663132260000000000);
-        contract.unlock_deposit(AccountId::from_str("roman").unwrap(), 0);
+        assert_eq!(
+            contract.unlock_deposit(AccountId::from_str("roman").unwrap(), 0),
+            Err(StickyHabitsError::TooEarlyToUnlock)
+        );
 
         // Failed unlock from beneficiary side - on too early
         set_context("josef", 0, 1663132260000000000);
-        contract.unlock_deposit(AccountId::from_str("roman").unwrap(), 0);
+        assert_eq!(
+            contract.unlock_deposit(AccountId::from_str("roman").unwrap(), 0),
+            Err(StickyHabitsError::TooEarlyToUnlock)
+        );
 
         // Success unlock from user side
         set_context("josef", 0, 1664302901000000000);
-        contract.approve_habit(AccountId::from_str("roman").unwrap(), 0);
+        contract
+            .approve_habit(AccountId::from_str("roman").unwrap(), 0)
+            .unwrap();
         set_context("roman", 0, 1665771701000000000);
-        contract.unlock_deposit(AccountId::from_str("roman").unwrap(), 0);
+        contract
+            .unlock_deposit(AccountId::from_str("roman").unwrap(), 0)
+            .unwrap();
 
         // Success unlock from beneficiary side
         set_context("roman", 20 * NEAR, 1662312790000000000);
-        contract.add_habit(
-            "Eat vegetarian food once a day".to_string(),
-            U64(0),
-            AccountId::from_str("josef").unwrap(),
-        );
+        contract
+            .add_habit(
+                "Eat vegetarian food once a day".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 1, 1662312790000000000);
         set_context("josef", 0, 1665771701000000000);
-        contract.unlock_deposit(AccountId::from_str("roman").unwrap(), 1);
+        contract
+            .unlock_deposit(AccountId::from_str("roman").unwrap(), 1)
+            .unwrap();
+    }
+
+    #[test]
+    fn generates_unique_ids() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 10 * NEAR, 1664172263000000000);
+        contract
+            .add_habit(
+                "Clean my keyboard once a week".to_string(),
+                U64(0),
+                AccountId::from_str("adam").unwrap(),
+                None,
+            )
+            .unwrap();
+        contract
+            .add_habit(
+                "Wake up every day at the same time".to_string(),
+                U64(0),
+                AccountId::from_str("maria").unwrap(),
+                None,
+            )
+            .unwrap();
+
+        let habits = &contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, Some(2))
+            .items;
+        assert_ne!(habits[0].id, habits[1].id);
+        assert_eq!(
+            contract
+                .get_habit(habits[1].id.clone())
+                .unwrap()
+                .description,
+            "Wake up every day at the same time".to_string()
+        );
+        assert!(contract.get_habit("unknown".to_string()).is_none());
+    }
+
+    #[test]
+    fn manages_habit_by_id() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+
+        contract
+            .update_evidence_by_id(
+                id.clone(),
+                "https://www.icloud.com/myfile.mov".to_string(),
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+
+        set_context("josef", 0, 1664302901000000000);
+        contract.approve_habit_by_id(id.clone()).unwrap();
+        assert_eq!(
+            contract.get_habit(id.clone()).unwrap().status,
+            HabitStatus::Approved
+        );
+
+        set_context("roman", 0, 1665771701000000000);
+        contract.unlock_deposit_by_id(id.clone()).unwrap();
+        assert_eq!(contract.get_habit(id).unwrap().deposit, U128(0));
+    }
+
+    #[test]
+    fn previews_settlement() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+
+        // Not approved, 5% goes to the developer and the rest to the beneficiary
+        let settlement = contract
+            .preview_settlement(AccountId::from_str("roman").unwrap(), id.clone())
+            .unwrap();
+        assert_eq!(settlement.to_user, U128(0));
+        assert_eq!(settlement.to_beneficiary, U128(19 * NEAR));
+        assert_eq!(settlement.to_developer, U128(NEAR));
+        assert_eq!(
+            u64::from(settlement.unlockable_at),
+            1662312790000000000
+                + contract.habit_acquisition_period
+                + contract.approval_grace_period
+        );
+
+        set_context("josef", 0, 1664302901000000000);
+        contract.approve_habit_by_id(id.clone()).unwrap();
+        let settlement = contract
+            .preview_settlement(AccountId::from_str("roman").unwrap(), id)
+            .unwrap();
+        assert_eq!(settlement.to_user, U128(20 * NEAR));
+        assert_eq!(settlement.to_beneficiary, U128(0));
+        assert_eq!(settlement.to_developer, U128(0));
+    }
+
+    // Auxiliary fn: parse NEP-297 events from the logs of the last call
+    fn get_events() -> Vec<near_sdk::serde_json::Value> {
+        near_sdk::test_utils::get_logs()
+            .iter()
+            .filter_map(|l| l.strip_prefix("EVENT_JSON:"))
+            .map(|l| near_sdk::serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn emits_lifecycle_events() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        let events = get_events();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["standard"], "stickyhabits");
+        assert_eq!(events[0]["version"], "1.0.0");
+        assert_eq!(events[0]["event"], "habit_created");
+        assert_eq!(events[0]["data"][0]["habit_id"], id.as_str());
+        assert_eq!(events[0]["data"][0]["beneficiaries"][0], "josef");
+        assert_eq!(events[0]["data"][0]["deposit"], (20 * NEAR).to_string());
+
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+        set_context("roman", 0, 1662312790000000000);
+        contract
+            .update_evidence(
+                AccountId::from_str("roman").unwrap(),
+                0,
+                "https://www.icloud.com/myfile.mov".to_string(),
+                None,
+            )
+            .unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "evidence_updated");
+        assert_eq!(
+            events[0]["data"][0]["evidence"],
+            "https://www.icloud.com/myfile.mov"
+        );
+
+        set_context("josef", 0, 1664302901000000000);
+        contract
+            .approve_habit(AccountId::from_str("roman").unwrap(), 0)
+            .unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "habit_approved");
+        assert_eq!(events[0]["data"][0]["user"], "roman");
+
+        set_context("roman", 0, 1665771701000000000);
+        contract
+            .unlock_deposit(AccountId::from_str("roman").unwrap(), 0)
+            .unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "deposit_unlocked");
+        assert_eq!(events[0]["data"][0]["amount"], (20 * NEAR).to_string());
+
+        // Not approved habit is forfeited to beneficiary and developer
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Eat vegetarian food once a day".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 1, 1662312790000000000);
+        set_context("josef", 0, 1665771701000000000);
+        contract
+            .unlock_deposit(AccountId::from_str("roman").unwrap(), 1)
+            .unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "deposit_forfeited");
+        assert_eq!(
+            events[0]["data"][0]["to_beneficiary"],
+            (19 * NEAR).to_string()
+        );
+        assert_eq!(events[0]["data"][0]["to_developer"], NEAR.to_string());
+
+        // Nothing to unlock anymore, no event
+        set_context("josef", 0, 1665771701000000000);
+        assert_eq!(
+            contract.unlock_deposit(AccountId::from_str("roman").unwrap(), 1),
+            Err(StickyHabitsError::AlreadySettled)
+        );
+        assert!(get_events().is_empty());
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 0, 1662312790000000000);
+        assert_eq!(
+            contract.add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None
+            ),
+            Err(StickyHabitsError::InsufficientDeposit(1))
+        );
+        assert_eq!(
+            contract.add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("roman").unwrap(),
+                None
+            ),
+            Err(StickyHabitsError::SameUserAndBeneficiary)
+        );
+
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(
+            contract.update_evidence(
+                AccountId::from_str("roman").unwrap(),
+                0,
+                "".to_string(),
+                None
+            ),
+            Err(StickyHabitsError::EmptyEvidence)
+        );
+        assert_eq!(
+            contract.update_evidence(
+                AccountId::from_str("roman").unwrap(),
+                3,
+                "https://www.icloud.com/myfile.mov".to_string(),
+                None
+            ),
+            Err(StickyHabitsError::IndexOutOfRange(3))
+        );
+        assert_eq!(
+            contract.approve_habit(AccountId::from_str("roman").unwrap(), 0),
+            Err(StickyHabitsError::NotBeneficiary)
+        );
+        assert_eq!(
+            contract.unlock_deposit_by_id("unknown".to_string()),
+            Err(StickyHabitsError::HabitNotFound("unknown".to_string()))
+        );
+
+        // Approval before beneficiary accepted the role
+        set_context("josef", 0, 1664302901000000000);
+        assert_eq!(
+            contract.approve_habit(AccountId::from_str("roman").unwrap(), 0),
+            Err(StickyHabitsError::BeneficiaryNotAccepted)
+        );
+
+        // Approval before deadline
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+        assert_eq!(
+            contract.approve_habit(AccountId::from_str("roman").unwrap(), 0),
+            Err(StickyHabitsError::OutsideApprovalWindow)
+        );
+        assert_eq!(
+            contract.approve_habit(AccountId::from_str("adam").unwrap(), 0),
+            Err(StickyHabitsError::UserHasNoHabits(
+                AccountId::from_str("adam").unwrap()
+            ))
+        );
+
+        // Approval after grace period
+        set_context("josef", 0, 1665771701000000000);
+        assert_eq!(
+            contract.approve_habit(AccountId::from_str("roman").unwrap(), 0),
+            Err(StickyHabitsError::OutsideApprovalWindow)
+        );
+
+        set_context("maria", 0, 1665771701000000000);
+        assert_eq!(
+            contract.unlock_deposit(AccountId::from_str("roman").unwrap(), 0),
+            Err(StickyHabitsError::NotUserOrBeneficiary)
+        );
+    }
+
+    #[test]
+    fn formats_error_codes() {
+        assert_eq!(
+            StickyHabitsError::TooEarlyToUnlock.to_string(),
+            "TOO_EARLY_TO_UNLOCK: Deposit can be unlocked after approval grace period"
+        );
+        assert_eq!(
+            StickyHabitsError::IndexOutOfRange(3).to_string(),
+            "INDEX_OUT_OF_RANGE: Index 3 is out of range"
+        );
+    }
+
+    #[test]
+    fn updates_config() {
+        set_context(OWNER, 0, 1662312790000000000);
+        let mut contract = StickyHabitsContract::init(
+            OWNER.parse().unwrap(),
+            U64(500),
+            U64(24 * 3600 * 1000000000),
+            U64(24 * 3600 * 1000000000),
+        );
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            contract.set_dev_fee_bps(U64(1000)),
+            Err(StickyHabitsError::NotOwner)
+        );
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+
+        set_context(OWNER, 1, 1662312790000000000);
+        assert_eq!(
+            contract.set_dev_fee_bps(U64(10001)),
+            Err(StickyHabitsError::InvalidDevFee(10001))
+        );
+        contract.set_dev_fee_bps(U64(1000)).unwrap();
+        contract
+            .set_habit_acquisition_period(U64(2 * 24 * 3600 * 1000000000))
+            .unwrap();
+        contract
+            .set_approval_grace_period(U64(3 * 24 * 3600 * 1000000000))
+            .unwrap();
+
+        let config = contract.get_config();
+        assert_eq!(config.dev_fee_bps, U64(1000));
+        assert_eq!(
+            config.habit_acquisition_period,
+            U64(2 * 24 * 3600 * 1000000000)
+        );
+        assert_eq!(
+            config.approval_grace_period,
+            U64(3 * 24 * 3600 * 1000000000)
+        );
+
+        // Existing habit is still settled under the terms it was created with
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+        let settlement = contract
+            .preview_settlement(AccountId::from_str("roman").unwrap(), id)
+            .unwrap();
+        assert_eq!(settlement.to_developer, U128(NEAR));
+        assert_eq!(
+            u64::from(settlement.unlockable_at),
+            1662312790000000000 + 2 * 24 * 3600 * 1000000000
+        );
+    }
+
+    #[test]
+    fn transfers_ownership() {
+        set_context(OWNER, 0, 1662312790000000000);
+        let mut contract = StickyHabitsContract::init(
+            OWNER.parse().unwrap(),
+            U64(500),
+            U64(24 * 3600 * 1000000000),
+            U64(24 * 3600 * 1000000000),
+        );
+
+        // Owner calls have to be signed with a full access key
+        assert_eq!(
+            contract.propose_owner(AccountId::from_str("maria").unwrap()),
+            Err(StickyHabitsError::OneYoctoRequired)
+        );
+        set_context(OWNER, 1, 1662312790000000000);
+        contract
+            .propose_owner(AccountId::from_str("maria").unwrap())
+            .unwrap();
+        assert_eq!(contract.get_config().owner, OWNER.parse().unwrap());
+
+        set_context("roman", 1, 1662312790000000000);
+        assert_eq!(
+            contract.accept_ownership(),
+            Err(StickyHabitsError::NotProposedOwner)
+        );
+
+        set_context("maria", 0, 1662312790000000000);
+        assert_eq!(
+            contract.accept_ownership(),
+            Err(StickyHabitsError::OneYoctoRequired)
+        );
+        set_context("maria", 1, 1662312790000000000);
+        contract.accept_ownership().unwrap();
+        let config = contract.get_config();
+        assert_eq!(config.owner, AccountId::from_str("maria").unwrap());
+        assert_eq!(config.proposed_owner, None);
+    }
+
+    #[test]
+    fn settles_under_original_terms() {
+        set_context(OWNER, 0, 1662312790000000000);
+        let mut contract = StickyHabitsContract::init(
+            OWNER.parse().unwrap(),
+            U64(500),
+            U64(24 * 3600 * 1000000000),
+            U64(24 * 3600 * 1000000000),
+        );
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+
+        set_context(OWNER, 1, 1662312790000000000);
+        contract.set_dev_fee_bps(U64(2000)).unwrap();
+        contract
+            .set_habit_acquisition_period(U64(7 * 24 * 3600 * 1000000000))
+            .unwrap();
+        contract
+            .set_approval_grace_period(U64(10 * 24 * 3600 * 1000000000))
+            .unwrap();
+
+        let habit = &contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0];
+        assert_eq!(
+            habit.terms,
+            HabitTerms {
+                dev_fee_bps: U64(500),
+                habit_acquisition_period: U64(24 * 3600 * 1000000000),
+                approval_grace_period: U64(24 * 3600 * 1000000000),
+                cancellation_period: U64(3600 * 1000000000),
+                cancellation_penalty_bps: U64(0),
+                acceptance_period: U64(3 * 24 * 3600 * 1000000000),
+                dispute_period: U64(0),
+            }
+        );
+
+        // Approval window closed one day after deadline, as agreed at creation
+        set_context("josef", 0, 1662312790000000000 + 3 * 24 * 3600 * 1000000000);
+        assert_eq!(
+            contract.approve_habit(AccountId::from_str("roman").unwrap(), 0),
+            Err(StickyHabitsError::OutsideApprovalWindow)
+        );
+
+        contract
+            .unlock_deposit(AccountId::from_str("roman").unwrap(), 0)
+            .unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "deposit_forfeited");
+        assert_eq!(events[0]["data"][0]["to_developer"], NEAR.to_string());
+    }
+
+    #[test]
+    fn cancels_habit() {
+        set_context(OWNER, 1, 1662312790000000000);
+        let mut contract = StickyHabitsContract::init(
+            OWNER.parse().unwrap(),
+            U64(500),
+            U64(24 * 3600 * 1000000000),
+            U64(24 * 3600 * 1000000000),
+        );
+        contract.set_cancellation_penalty_bps(U64(100)).unwrap();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        for description in ["Do 15 push-ups everyday", "Do 15 push-ups every day"] {
+            contract
+                .add_habit(
+                    description.to_string(),
+                    U64(0),
+                    AccountId::from_str("josef").unwrap(),
+                    None,
+                )
+                .unwrap();
+        }
+        let habits = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, Some(2))
+            .items;
+
+        set_context("josef", 0, 1662312790000000000);
+        assert_eq!(
+            contract.cancel_habit(habits[0].id.clone()),
+            Err(StickyHabitsError::NotHabitOwner)
+        );
+
+        // Refund minus 1% penalty within the cool-off period
+        set_context("roman", 0, 1662312790000000000 + 1800 * 1000000000);
+        contract.cancel_habit(habits[0].id.clone()).unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "habit_cancelled");
+        assert_eq!(
+            events[0]["data"][0]["refund"],
+            (20 * NEAR - NEAR / 5).to_string()
+        );
+        assert_eq!(events[0]["data"][0]["penalty"], (NEAR / 5).to_string());
+
+        let cancelled = contract.get_habit(habits[0].id.clone()).unwrap();
+        assert_eq!(cancelled.status, HabitStatus::Cancelled);
+        assert_eq!(cancelled.deposit, U128(0));
+        assert_eq!(
+            contract.cancel_habit(habits[0].id.clone()),
+            Err(StickyHabitsError::AlreadySettled)
+        );
+
+        contract
+            .update_evidence_by_id(
+                habits[1].id.clone(),
+                "https://www.icloud.com/myfile.mov".to_string(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            contract.cancel_habit(habits[1].id.clone()),
+            Err(StickyHabitsError::EvidenceAlreadySubmitted)
+        );
+    }
+
+    #[test]
+    fn closes_cancellation_period() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+
+        set_context("roman", 0, 1662312790000000000 + 7200 * 1000000000);
+        assert_eq!(
+            contract.cancel_habit(id),
+            Err(StickyHabitsError::CancellationPeriodOver)
+        );
+    }
+
+    #[test]
+    fn accepts_beneficiary_role() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+
+        // Habit waits in invitations until beneficiary accepts
+        let invitations = contract.get_invitations(AccountId::from_str("josef").unwrap());
+        assert_eq!(
+            invitations[&AccountId::from_str("roman").unwrap()][0].id,
+            id
+        );
+        assert!(contract
+            .get_habits_beneficiary(AccountId::from_str("josef").unwrap(), None, None)
+            .items
+            .is_empty());
+
+        set_context("adam", 0, 1662312790000000000);
+        assert_eq!(
+            contract.accept_beneficiary_role(id.clone()),
+            Err(StickyHabitsError::NotBeneficiary)
+        );
+
+        set_context("josef", 0, 1662312790000000000);
+        contract.accept_beneficiary_role(id.clone()).unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "beneficiary_accepted");
+        assert_eq!(events[0]["data"][0]["habit_id"], id.as_str());
+
+        assert!(contract
+            .get_invitations(AccountId::from_str("josef").unwrap())
+            .is_empty());
+        assert!(contract
+            .get_habits_beneficiary(AccountId::from_str("josef").unwrap(), None, None)
+            .items
+            .iter()
+            .any(|h| h.user.as_str() == "roman"));
+        assert_eq!(
+            contract.accept_beneficiary_role(id.clone()),
+            Err(StickyHabitsError::BeneficiaryAlreadyAccepted)
+        );
+        assert_eq!(
+            contract.decline_beneficiary_role(id),
+            Err(StickyHabitsError::BeneficiaryAlreadyAccepted)
+        );
+    }
+
+    #[test]
+    fn declines_beneficiary_role() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+
+        set_context("josef", 0, 1662312790000000000);
+        contract.decline_beneficiary_role(id.clone()).unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "beneficiary_declined");
+        assert_eq!(events[0]["data"][0]["refund"], (20 * NEAR).to_string());
+
+        assert_eq!(contract.get_habit(id.clone()).unwrap().deposit, U128(0));
+        assert!(contract
+            .get_invitations(AccountId::from_str("josef").unwrap())
+            .is_empty());
+        assert_eq!(
+            contract.accept_beneficiary_role(id),
+            Err(StickyHabitsError::AlreadySettled)
+        );
+    }
+
+    #[test]
+    fn refunds_unaccepted_habit() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+
+        set_context("roman", 0, 1662312790000000000 + 24 * 3600 * 1000000000);
+        assert_eq!(
+            contract.unlock_deposit_by_id(id.clone()),
+            Err(StickyHabitsError::TooEarlyToUnlock)
+        );
+
+        // Acceptance period is over, whole deposit goes back to the user
+        set_context("josef", 0, 1662312790000000000 + 4 * 24 * 3600 * 1000000000);
+        assert_eq!(
+            contract.accept_beneficiary_role(id.clone()),
+            Err(StickyHabitsError::AcceptancePeriodOver)
+        );
+        set_context("roman", 0, 1662312790000000000 + 4 * 24 * 3600 * 1000000000);
+        contract.unlock_deposit_by_id(id.clone()).unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "deposit_unlocked");
+        assert_eq!(events[0]["data"][0]["amount"], (20 * NEAR).to_string());
+        assert!(contract
+            .get_invitations(AccountId::from_str("josef").unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn tracks_habit_status() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        for description in ["Do 15 push-ups everyday", "Eat vegetarian food once a day"] {
+            contract
+                .add_habit(
+                    description.to_string(),
+                    U64(0),
+                    AccountId::from_str("josef").unwrap(),
+                    None,
+                )
+                .unwrap();
+        }
+        set_context("roman", 10 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Read 10 pages every evening".to_string(),
+                U64(0),
+                AccountId::from_str("adam").unwrap(),
+                None,
+            )
+            .unwrap();
+        let habits = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, Some(3))
+            .items;
+        for (index, habit) in habits.iter().enumerate().take(2) {
+            accept_role(&mut contract, "roman", index as u16, 1662312790000000000);
+            assert_eq!(habit.status, HabitStatus::Pending);
+        }
+
+        set_context("roman", 0, 1662312790000000000);
+        contract
+            .update_evidence_by_id(
+                habits[0].id.clone(),
+                "https://www.icloud.com/myfile.mov".to_string(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            contract.get_habit(habits[0].id.clone()).unwrap().status,
+            HabitStatus::EvidenceSubmitted
+        );
+
+        // Approved habit cannot be approved again or get new evidence
+        set_context("josef", 0, 1664302901000000000);
+        contract.approve_habit_by_id(habits[0].id.clone()).unwrap();
+        assert_eq!(
+            contract.approve_habit_by_id(habits[0].id.clone()),
+            Err(StickyHabitsError::InvalidStatus(HabitStatus::Approved))
+        );
+        set_context("roman", 0, 1664302901000000000);
+        assert_eq!(
+            contract.update_evidence_by_id(
+                habits[0].id.clone(),
+                "https://www.icloud.com/otherfile.mov".to_string(),
+                None
+            ),
+            Err(StickyHabitsError::InvalidStatus(HabitStatus::Approved))
+        );
+
+        // Beneficiary declined the role of the third habit
+        set_context("adam", 0, 1662312790000000000);
+        contract
+            .decline_beneficiary_role(habits[2].id.clone())
+            .unwrap();
+
+        set_context("roman", 0, 1665771701000000000);
+        contract.unlock_deposit_by_id(habits[0].id.clone()).unwrap();
+        contract.unlock_deposit_by_id(habits[1].id.clone()).unwrap();
+
+        let roman = AccountId::from_str("roman").unwrap();
+        let refunded = contract
+            .get_habits_user_by_status(roman.clone(), HabitStatus::Refunded, None, Some(3))
+            .items;
+        assert_eq!(refunded.len(), 2);
+        assert_eq!(refunded[0].id, habits[0].id);
+        assert_eq!(refunded[1].id, habits[2].id);
+        let forfeited = contract
+            .get_habits_user_by_status(roman.clone(), HabitStatus::Forfeited, None, None)
+            .items;
+        assert_eq!(forfeited[0].id, habits[1].id);
+        assert!(contract
+            .get_habits_user_by_status(roman.clone(), HabitStatus::Pending, None, None)
+            .items
+            .is_empty());
+
+        // Next page continues after the last habit read, filtered out habits are skipped
+        let page =
+            contract.get_habits_user_by_status(roman.clone(), HabitStatus::Refunded, None, Some(1));
+        assert_eq!(page.items[0].id, habits[0].id);
+        let page = contract.get_habits_user_by_status(
+            roman,
+            HabitStatus::Refunded,
+            page.next_cursor,
+            Some(1),
+        );
+        assert_eq!(page.items[0].id, habits[2].id);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn rejects_habit() {
+        set_context(OWNER, 1, 1662312790000000000);
+        let mut contract = StickyHabitsContract::init(
+            OWNER.parse().unwrap(),
+            U64(500),
+            U64(24 * 3600 * 1000000000),
+            U64(24 * 3600 * 1000000000),
+        );
+        contract
+            .set_dispute_period(U64(2 * 24 * 3600 * 1000000000))
+            .unwrap();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+
+        set_context("josef", 0, 1662312790000000000);
+        assert_eq!(
+            contract.reject_habit_by_id(id.clone(), "No push-ups seen".to_string()),
+            Err(StickyHabitsError::BeneficiaryNotAccepted)
+        );
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+        assert_eq!(
+            contract.reject_habit(AccountId::from_str("roman").unwrap(), 0, "".to_string()),
+            Err(StickyHabitsError::EmptyRejectionReason)
+        );
+
+        // Rejected before deadline, deposit is locked for the dispute period only
+        set_context("josef", 0, 1662312790000000000 + 3600 * 1000000000);
+        contract
+            .reject_habit(
+                AccountId::from_str("roman").unwrap(),
+                0,
+                "No push-ups seen".to_string(),
+            )
+            .unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "habit_rejected");
+        assert_eq!(events[0]["data"][0]["reason"], "No push-ups seen");
+
+        let habit = contract.get_habit(id.clone()).unwrap();
+        assert_eq!(habit.status, HabitStatus::Rejected);
+        assert_eq!(
+            habit.rejection.unwrap().rejected_at,
+            U64(1662312790000000000 + 3600 * 1000000000)
+        );
+        assert_eq!(
+            contract.approve_habit_by_id(id.clone()),
+            Err(StickyHabitsError::OutsideApprovalWindow)
+        );
+
+        set_context("roman", 0, 1662312790000000000 + 24 * 3600 * 1000000000);
+        assert_eq!(
+            contract.unlock_deposit_by_id(id.clone()),
+            Err(StickyHabitsError::TooEarlyToUnlock)
+        );
+
+        set_context("josef", 0, 1662312790000000000 + 3 * 24 * 3600 * 1000000000);
+        contract.unlock_deposit_by_id(id.clone()).unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "deposit_forfeited");
+        assert_eq!(
+            contract.get_habit(id).unwrap().status,
+            HabitStatus::Forfeited
+        );
+    }
+
+    #[test]
+    fn closes_rejection_period() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+
+        // Without dispute period, rejected deposit is unlockable right away
+        set_context("josef", 0, 1663312790000000000);
+        contract
+            .reject_habit(
+                AccountId::from_str("roman").unwrap(),
+                0,
+                "No push-ups seen".to_string(),
+            )
+            .unwrap();
+        set_context("josef", 0, 1663312790000000001);
+        contract
+            .unlock_deposit(AccountId::from_str("roman").unwrap(), 0)
+            .unwrap();
+
+        set_context("roman", 20 * NEAR, 1662312790000000000);
+        contract
+            .add_habit(
+                "Eat vegetarian food once a day".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 1, 1662312790000000000);
+        set_context("josef", 0, 1665771701000000000);
+        assert_eq!(
+            contract.reject_habit(
+                AccountId::from_str("roman").unwrap(),
+                1,
+                "No vegetables seen".to_string()
+            ),
+            Err(StickyHabitsError::RejectionPeriodOver)
+        );
+    }
+
+    #[test]
+    fn approves_group_habit() {
+        let mut contract = StickyHabitsContract::default();
+        let group = vec![
+            AccountId::from_str("josef").unwrap(),
+            AccountId::from_str("adam").unwrap(),
+            AccountId::from_str("maria").unwrap(),
+        ];
+
+        register(&mut contract, "roman");
+        set_context("roman", 30 * NEAR, 1662312790000000000);
+        assert_eq!(
+            contract.add_group_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                group.clone(),
+                4,
+                None
+            ),
+            Err(StickyHabitsError::InvalidApprovalThreshold(4))
+        );
+        assert_eq!(
+            contract.add_group_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                vec![group[0].clone(), group[0].clone()],
+                1,
+                None
+            ),
+            Err(StickyHabitsError::DuplicateBeneficiary(group[0].clone()))
+        );
+        contract
+            .add_group_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                group.clone(),
+                2,
+                None,
+            )
+            .unwrap();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+        assert_eq!(
+            contract
+                .get_invitations(group[2].clone())
+                .get(&AccountId::from_str("roman").unwrap())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Stake is binding only after all beneficiaries accept
+        set_context("josef", 0, 1662312790000000000);
+        contract.accept_beneficiary_role(id.clone()).unwrap();
+        assert!(!contract.get_habit(id.clone()).unwrap().beneficiary_accepted);
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+        assert!(contract.get_habit(id.clone()).unwrap().beneficiary_accepted);
+        for beneficiary in group.iter() {
+            assert!(contract
+                .get_habits_beneficiary(beneficiary.clone(), None, None)
+                .items
+                .iter()
+                .any(|h| h.user.as_str() == "roman"));
+        }
+
+        set_context("josef", 0, 1664302901000000000);
+        contract.approve_habit_by_id(id.clone()).unwrap();
+        assert_eq!(get_events()[0]["event"], "vote_recorded");
+        assert_eq!(
+            contract.approve_habit_by_id(id.clone()),
+            Err(StickyHabitsError::AlreadyVoted)
+        );
+
+        // One rejection does not block 2-of-3 approval
+        set_context("adam", 0, 1664302901000000000);
+        contract
+            .reject_habit_by_id(id.clone(), "No push-ups seen".to_string())
+            .unwrap();
+        assert_eq!(
+            contract.get_habit(id.clone()).unwrap().status,
+            HabitStatus::Pending
+        );
+
+        set_context("maria", 0, 1664302901000000000);
+        contract.approve_habit_by_id(id.clone()).unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "habit_approved");
+        assert_eq!(events[0]["data"][0]["beneficiary"], "maria");
+        assert_eq!(
+            contract.get_habit(id).unwrap().status,
+            HabitStatus::Approved
+        );
+    }
+
+    #[test]
+    fn splits_forfeit_among_beneficiaries() {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 30 * NEAR, 1662312790000000000);
+        contract
+            .add_group_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                vec![
+                    AccountId::from_str("josef").unwrap(),
+                    AccountId::from_str("adam").unwrap(),
+                    AccountId::from_str("maria").unwrap(),
+                ],
+                2,
+                None,
+            )
+            .unwrap();
+        accept_role(&mut contract, "roman", 0, 1662312790000000000);
+
+        // Second rejection makes 2-of-3 approval impossible
+        for beneficiary in ["josef", "adam"] {
+            set_context(beneficiary, 0, 1664302901000000000);
+            contract
+                .reject_habit(
+                    AccountId::from_str("roman").unwrap(),
+                    0,
+                    "No push-ups seen".to_string(),
+                )
+                .unwrap();
+        }
+        assert_eq!(get_events()[0]["event"], "habit_rejected");
+
+        set_context("maria", 0, 1664302901000000001);
+        contract
+            .unlock_deposit(AccountId::from_str("roman").unwrap(), 0)
+            .unwrap();
+        let events = get_events();
+        assert_eq!(events[0]["event"], "deposit_forfeited");
+        let data = events[0]["data"].as_array().unwrap();
+        assert_eq!(data.len(), 3);
+        // 5% of 30 NEAR goes to the developer, the rest is split evenly
+        for entry in data {
+            assert_eq!(entry["to_beneficiary"], (95 * NEAR / 10).to_string());
+        }
+        assert_eq!(data[0]["to_developer"], (3 * NEAR / 2).to_string());
+        assert_eq!(data[1]["to_developer"], "0");
     }
 }
@@ -0,0 +1,321 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{UnorderedMap, Vector};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, near_bindgen, AccountId, Balance, FunctionError, Gas, GasWeight, Promise};
+
+use crate::storage::StorageAccount;
+use crate::{
+    Habit, HabitStatus, HabitTerms, StickyHabitsContract, StickyHabitsContractExt,
+    StickyHabitsError, Vote,
+};
+
+// Version of the state layout written by this code. State deployed before versioning has
+// no version stored and is version 1.
+pub const STATE_VERSION: u16 = 2;
+const VERSION_KEY: &[u8] = b"version";
+// Flat storage fee V1 took from the first deposit of each user
+const V1_STORAGE_COST: Balance = 1_000_000_000_000_000_000_000;
+
+// Stores the version of the state layout next to the contract state
+pub(crate) fn write_state_version() {
+    env::storage_write(VERSION_KEY, &STATE_VERSION.try_to_vec().unwrap());
+}
+
+fn read_state_version() -> u16 {
+    env::storage_read(VERSION_KEY).map_or(1, |version| u16::try_from_slice(&version).unwrap())
+}
+
+// Habit layout of state version 1
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct HabitV1 {
+    pub id: String,
+    pub description: String,
+    pub deadline: U64,
+    pub deposit: U128,
+    pub beneficiary: AccountId,
+    pub evidence: String,
+    pub approved: bool,
+}
+
+// Contract state of version 1, stored under the same "STATE" key
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StickyHabitsContractV1 {
+    pub owner: AccountId,
+    pub balance: Balance,
+    pub dev_fee: u64,
+    // percent
+    pub id_counter: u64,
+    pub habit_acquisition_period: u64,
+    pub approval_grace_period: u64,
+    pub habits: UnorderedMap<AccountId, Vector<HabitV1>>,
+    pub beneficiaries: UnorderedMap<AccountId, Vector<AccountId>>,
+}
+
+impl HabitV1 {
+    // V1 habits were created under contract settings in force at the time of migration
+    fn into_current(self, id: String, seq: u64, terms: HabitTerms) -> Habit {
+        // V1 zeroed the deposit on unlock, approval decided who received it
+        let status = match (self.deposit.0 == 0, self.approved) {
+            (true, true) => HabitStatus::Refunded,
+            (true, false) => HabitStatus::Forfeited,
+            (false, true) => HabitStatus::Approved,
+            (false, false) if !self.evidence.is_empty() => HabitStatus::EvidenceSubmitted,
+            (false, false) => HabitStatus::Pending,
+        };
+        // V1 approval was a single beneficiary's vote
+        let votes = if self.approved {
+            vec![Vote {
+                beneficiary: self.beneficiary.clone(),
+                approved: true,
+                reason: None,
+            }]
+        } else {
+            vec![]
+        };
+        Habit {
+            id,
+            description: self.description,
+            deadline: self.deadline,
+            deposit: self.deposit,
+            beneficiaries: vec![self.beneficiary.clone()],
+            evidence: self.evidence,
+            status,
+            // Creation time was not recorded in V1
+            created_at: U64(0),
+            // Stakes and beneficiary links of V1 habits were binding from creation
+            beneficiary_accepted: true,
+            terms,
+            rejection: None,
+            approval_threshold: 1,
+            accepted_by: vec![self.beneficiary],
+            votes,
+            schedule: None,
+            token: None,
+            seq: U64(seq),
+            private: false,
+            settlement_seq: None,
+            disputed_for: U64(0),
+        }
+    }
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // Rewrites state of the stored version into the current layout, called by upgrade after
+    // the new code is deployed. Current state is kept as it is.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        if !env::state_exists() {
+            StickyHabitsError::NotInitialized.panic();
+        }
+        let contract = match read_state_version() {
+            1 => Self::migrate_v1(),
+            STATE_VERSION => env::state_read().unwrap(),
+            version => StickyHabitsError::UnsupportedStateVersion(version).panic(),
+        };
+        write_state_version();
+
+        contract
+    }
+
+    // Deploys new contract code passed as raw input of the call and migrates the state
+    #[payable]
+    #[handle_result]
+    pub fn upgrade(&mut self) -> Result<Promise, StickyHabitsError> {
+        self.check_owner()?;
+        let code = env::input().unwrap_or_default();
+
+        Ok(Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call_weight("migrate".to_string(), vec![], 0, Gas(0), GasWeight(1)))
+    }
+}
+
+impl StickyHabitsContract {
+    // Converts state of version 1, its habits get unique ids and current terms
+    fn migrate_v1() -> Self {
+        let old: StickyHabitsContractV1 = env::state_read().unwrap();
+
+        // Vector<HabitV1> and Vector<Habit> only differ in element type, map layout is the same
+        let habits: UnorderedMap<AccountId, Vector<Habit>> =
+            UnorderedMap::try_from_slice(&old.habits.try_to_vec().unwrap()).unwrap();
+
+        let mut contract = Self {
+            owner: old.owner,
+            proposed_owner: None,
+            balance: old.balance,
+            dev_fee_bps: old.dev_fee * 100,
+            id_counter: old.id_counter,
+            habit_acquisition_period: old.habit_acquisition_period,
+            approval_grace_period: old.approval_grace_period,
+            habits,
+            beneficiaries: old.beneficiaries,
+            ..Default::default()
+        };
+
+        let users: Vec<AccountId> = old.habits.keys().collect();
+        for user in users {
+            let legacy_habits: Vec<HabitV1> = match old.habits.get(&user) {
+                Some(v) => v.iter().collect(),
+                None => continue,
+            };
+
+            // Same prefix as the V1 vector, elements are overwritten in place
+            let mut migrated_habits: Vector<Habit> = Vector::new(
+                ("vector-h-id-".to_string() + user.as_str())
+                    .as_bytes()
+                    .to_vec(),
+            );
+            for habit in legacy_habits {
+                // V1 ids are all equal, every habit gets a new unique one
+                let seq = contract.id_counter;
+                let id = match contract.next_habit_id() {
+                    Ok(id) => id,
+                    Err(err) => err.panic(),
+                };
+                let terms = contract.current_terms();
+                let habit = habit.into_current(id.clone(), seq, terms);
+                migrated_habits.push(&habit);
+                contract
+                    .habit_index
+                    .insert(&id, &(user.clone(), migrated_habits.len() - 1));
+                contract.add_beneficiary_habit(&habit.beneficiaries[0], &habit);
+                contract.record_creation(seq, &habit.id);
+            }
+            contract.habits.insert(&user, &migrated_habits);
+
+            // Storage fee the user paid to V1 becomes the storage deposit
+            contract.storage_accounts.insert(
+                &user,
+                &StorageAccount {
+                    total: V1_STORAGE_COST,
+                    used: 0,
+                },
+            );
+            contract.storage_deposits += V1_STORAGE_COST;
+        }
+
+        contract
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageBalance;
+    use crate::tests::{add_habit, register, set_context, DAY, NEAR, OWNER, START};
+    use sha256::digest;
+    use std::str::FromStr;
+
+    fn legacy_habit(description: &str, beneficiary: &str) -> HabitV1 {
+        HabitV1 {
+            id: digest(&[] as &[u8]),
+            description: description.to_string(),
+            deadline: U64(1664172263000000000),
+            deposit: U128(10 * NEAR),
+            beneficiary: AccountId::from_str(beneficiary).unwrap(),
+            evidence: "".to_string(),
+            approved: false,
+        }
+    }
+
+    #[test]
+    fn migrates_v1_state() {
+        set_context(env::current_account_id().as_str(), 0, START);
+
+        let roman = AccountId::from_str("roman").unwrap();
+        let mut habits = UnorderedMap::new(b"map-id-1".to_vec());
+        let mut romans_habits = Vector::new(b"vector-h-id-roman".to_vec());
+        romans_habits.push(&legacy_habit("Clean my keyboard once a week", "adam"));
+        romans_habits.push(&legacy_habit("Eat two tomatoes every day", "maria"));
+        habits.insert(&roman, &romans_habits);
+
+        env::state_write(&StickyHabitsContractV1 {
+            owner: AccountId::from_str(OWNER).unwrap(),
+            balance: 20 * NEAR,
+            dev_fee: 5,
+            id_counter: 2,
+            habit_acquisition_period: 21 * DAY,
+            approval_grace_period: 15 * DAY,
+            habits,
+            beneficiaries: UnorderedMap::new(b"map-id-2".to_vec()),
+        });
+
+        let mut contract = StickyHabitsContract::migrate();
+        assert_eq!(contract.get_config().dev_fee_bps, U64(500));
+
+        let migrated = contract.get_habits_user(roman.clone(), None, Some(2)).items;
+        assert_eq!(migrated.len(), 2);
+        assert_ne!(migrated[0].id, migrated[1].id);
+        assert_eq!(migrated[1].description, "Eat two tomatoes every day");
+        assert_eq!(migrated[1].terms.dev_fee_bps, U64(500));
+        assert_eq!(migrated[1].status, HabitStatus::Pending);
+
+        let by_id = contract.get_habit(migrated[1].id.clone()).unwrap();
+        assert_eq!(
+            by_id.beneficiaries,
+            vec![AccountId::from_str("maria").unwrap()]
+        );
+        assert_eq!(by_id.deposit, U128(10 * NEAR));
+
+        // V1 users keep their storage fee and can add evidence to migrated habits
+        assert_eq!(
+            contract.storage_balance_of(roman.clone()),
+            Some(StorageBalance {
+                total: U128(V1_STORAGE_COST),
+                available: U128(V1_STORAGE_COST),
+            })
+        );
+        set_context("roman", 0, START);
+        contract
+            .update_evidence_by_id(
+                migrated[1].id.clone(),
+                "https://www.icloud.com/tomatoes.mov".to_string(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(contract.get_recent_habits(None, None).items.len(), 2);
+
+        // Migrated state is current, following upgrade keeps it
+        env::state_write(&contract);
+        let contract = StickyHabitsContract::migrate();
+        let again = contract.get_habits_user(roman, None, Some(2)).items;
+        assert_eq!(again[1].id, migrated[1].id);
+    }
+
+    #[test]
+    fn keeps_current_state() {
+        set_context(OWNER, 0, START);
+        let roman = AccountId::from_str("roman").unwrap();
+        // Default state is created without writes, so that views work before init
+        let _ = StickyHabitsContract::default();
+        assert!(env::storage_read(VERSION_KEY).is_none());
+
+        let mut contract = StickyHabitsContract::init(
+            OWNER.parse().unwrap(),
+            U64(500),
+            U64(21 * DAY),
+            U64(15 * DAY),
+        );
+        assert_eq!(read_state_version(), STATE_VERSION);
+        register(&mut contract, "roman");
+        add_habit(
+            &mut contract,
+            "roman",
+            "Do 15 push-ups everyday",
+            "josef",
+            NEAR,
+        );
+        let habits = contract.get_habits_user(roman.clone(), None, None).items;
+        env::state_write(&contract);
+
+        let contract = StickyHabitsContract::migrate();
+        let migrated = contract.get_habits_user(roman, None, None).items;
+        assert_eq!(migrated.len(), 1);
+        assert_eq!(migrated[0].id, habits[0].id);
+        assert_eq!(migrated[0].seq, habits[0].seq);
+        assert_eq!(contract.id_counter, 1);
+        assert_eq!(contract.get_balance(), Ok(U128(NEAR)));
+    }
+}
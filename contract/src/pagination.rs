@@ -0,0 +1,173 @@
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::collections::Vector;
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::AccountId;
+
+use crate::{Habit, StickyHabitsContract};
+
+// Number of items per page when limit is not given, and the upper bound of the limit
+pub const DEFAULT_PAGE_LIMIT: u16 = 10;
+pub const MAX_PAGE_LIMIT: u16 = 100;
+// Entries read for one page per item of the limit, bounds the gas of filtered listings
+pub const SCANNED_PER_ITEM: usize = 4;
+
+// Habit together with the user who made it
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UserHabit {
+    pub user: AccountId,
+    pub habit: Habit,
+}
+
+// Items in the order of the listing. Pass next_cursor to get the following page, it is None
+// after the last page. Page may hold fewer items than the limit, or none, when entries were
+// filtered out.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<U64>,
+}
+
+// Collects page from entries that follow the cursor, keyed by their position in the listing.
+// Entries without item are filtered out, at most SCANNED_PER_ITEM entries per item of the limit
+// are read. Next cursor is the key of the last entry read.
+pub(crate) fn paginate<T>(
+    entries: impl Iterator<Item = (u64, Option<T>)>,
+    limit_to: Option<u16>,
+) -> Page<T> {
+    let limit = usize::from(limit_to.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT));
+    let mut entries = entries.peekable();
+
+    let mut items = vec![];
+    let mut last_key = None;
+    let mut scanned = 0;
+    while items.len() < limit && scanned < limit * SCANNED_PER_ITEM {
+        let (key, item) = match entries.next() {
+            Some(entry) => entry,
+            None => break,
+        };
+        items.extend(item);
+        last_key = Some(key);
+        scanned += 1;
+    }
+
+    Page {
+        items,
+        next_cursor: match entries.peek() {
+            Some(_) => last_key.map(U64),
+            None => None,
+        },
+    }
+}
+
+// Collects page of an append-only vector, cursor is the position of the last item returned
+pub(crate) fn vector_page<T: BorshSerialize + BorshDeserialize>(
+    vector: &Vector<T>,
+    cursor: Option<U64>,
+    limit_to: Option<u16>,
+) -> Page<T> {
+    let start = cursor.map_or(0, |cursor| u64::from(cursor) + 1);
+
+    paginate(
+        (start..vector.len()).map(|position| (position, vector.get(position))),
+        limit_to,
+    )
+}
+
+// First position after the cursor in a listing sorted by key
+pub(crate) fn start_after(len: u64, cursor: Option<U64>, key_at: impl Fn(u64) -> u64) -> u64 {
+    let cursor = match cursor {
+        Some(cursor) => u64::from(cursor),
+        None => return 0,
+    };
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if key_at(middle) <= cursor {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+impl StickyHabitsContract {
+    // User's habits are kept in creation order, page starts right after the cursor
+    pub(crate) fn user_habits_page(
+        &self,
+        user: &AccountId,
+        cursor: Option<U64>,
+        limit_to: Option<u16>,
+        filter: impl Fn(&Habit) -> bool,
+    ) -> Page<Habit> {
+        let existing_habits = match self.habits.get(user) {
+            Some(v) => v,
+            None => return paginate(std::iter::empty(), limit_to),
+        };
+        let start = start_after(existing_habits.len(), cursor, |index| {
+            u64::from(existing_habits.get(index).unwrap().seq)
+        });
+
+        paginate(
+            (start..existing_habits.len()).map(|index| {
+                let habit = existing_habits.get(index).unwrap();
+                (u64::from(habit.seq), Some(habit).filter(|h| filter(h)))
+            }),
+            limit_to,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paginates_from_cursor() {
+        let keys = [1, 2, 3, 4, 7];
+        let page_after = |cursor: Option<U64>, limit_to| {
+            let start = start_after(keys.len() as u64, cursor, |i| keys[i as usize]);
+            paginate(
+                keys[start as usize..].iter().map(|k| (*k, Some(*k))),
+                limit_to,
+            )
+        };
+
+        let page = page_after(None, Some(2));
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(page.next_cursor, Some(U64(2)));
+        let page = page_after(page.next_cursor, Some(2));
+        assert_eq!(page.items, vec![3, 4]);
+        let page = page_after(page.next_cursor, Some(2));
+        assert_eq!(page.items, vec![7]);
+        assert_eq!(page.next_cursor, None);
+
+        // Removed entry does not shift the following pages
+        let page = page_after(Some(U64(5)), None);
+        assert_eq!(page.items, vec![7]);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn bounds_filtered_scan() {
+        // Only every tenth entry passes the filter
+        let entries = || (1..=100).map(|k| (k, if k % 10 == 0 { Some(k) } else { None }));
+
+        let page = paginate(entries(), Some(2));
+        assert_eq!(page.items, Vec::<u64>::new());
+        assert_eq!(page.next_cursor, Some(U64(8)));
+        let page = paginate(entries().skip(8), Some(2));
+        assert_eq!(page.items, vec![10]);
+        assert_eq!(page.next_cursor, Some(U64(16)));
+
+        let page = paginate(entries(), None);
+        assert_eq!(page.items, vec![10, 20, 30, 40]);
+        assert_eq!(page.next_cursor, Some(U64(40)));
+        let page = paginate(entries().skip(90), None);
+        assert_eq!(page.items, vec![100]);
+        assert_eq!(page.next_cursor, None);
+    }
+}
@@ -0,0 +1,340 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::events::{CheckInApprovedData, CheckInSubmittedData, HabitEvent};
+use crate::{HabitStatus, StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+// Upper bound of periods of one habit, a year of daily check-ins
+pub const MAX_PERIODS: u16 = 366;
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, BorshDeserialize, BorshSerialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Cadence {
+    Daily,
+    Weekly,
+}
+
+impl Cadence {
+    // Length of one period in nanoseconds
+    pub fn period_length(&self) -> u64 {
+        match self {
+            Self::Daily => 24 * 3600 * 1000000000,
+            Self::Weekly => 7 * 24 * 3600 * 1000000000,
+        }
+    }
+}
+
+// Recurring commitment, periods follow each other from habit creation until deadline
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Schedule {
+    pub cadence: Cadence,
+    // One check-in per period
+    pub check_ins: Vec<CheckIn>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CheckIn {
+    pub evidence: String,
+    pub approvals: Vec<AccountId>,
+}
+
+impl Schedule {
+    pub fn new(cadence: Cadence, periods: u16) -> Self {
+        Self {
+            cadence,
+            check_ins: vec![
+                CheckIn {
+                    evidence: "".to_string(),
+                    approvals: vec![],
+                };
+                usize::from(periods)
+            ],
+        }
+    }
+
+    pub fn periods(&self) -> u64 {
+        self.check_ins.len() as u64
+    }
+
+    // Number of periods approved by at least approval_threshold beneficiaries
+    pub fn approved_periods(&self, approval_threshold: u16) -> u64 {
+        self.check_ins
+            .iter()
+            .filter(|c| c.approvals.len() >= usize::from(approval_threshold))
+            .count() as u64
+    }
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // Adds new habit with evidence and approval per period, deposit is returned
+    // proportionally to the periods approved
+    #[payable]
+    #[handle_result]
+    pub fn add_periodic_habit(
+        &mut self,
+        description: String,
+        beneficiaries: Vec<AccountId>,
+        approval_threshold: u16,
+        cadence: Cadence,
+        periods: u16,
+        private: Option<bool>,
+    ) -> Result<(), StickyHabitsError> {
+        if periods == 0 || periods > MAX_PERIODS {
+            return Err(StickyHabitsError::InvalidPeriods(periods));
+        }
+        self.internal_add_habit(
+            crate::Stake::attached(),
+            description,
+            U64(0),
+            beneficiaries,
+            approval_threshold,
+            Some(Schedule::new(cadence, periods)),
+            private.unwrap_or(false),
+        )
+    }
+
+    // Adds a link to the evidence of the current period, the link is stored in the check-in
+    // and in the evidence history
+    #[handle_result]
+    pub fn submit_check_in(
+        &mut self,
+        id: String,
+        evidence: String,
+    ) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+        let current_time = env::block_timestamp();
+
+        self.validate_evidence(&evidence)?;
+        let initial_storage = self.accounted_storage_usage();
+        let (user, index) = self.habit_location(&id)?;
+        if user != account {
+            return Err(StickyHabitsError::NotHabitOwner);
+        }
+
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        if habit.status.is_settled() {
+            return Err(StickyHabitsError::AlreadySettled);
+        }
+        if !matches!(
+            habit.status,
+            HabitStatus::Pending | HabitStatus::EvidenceSubmitted
+        ) {
+            return Err(StickyHabitsError::InvalidStatus(habit.status));
+        }
+        let created_at = u64::from(habit.created_at);
+        let schedule = habit
+            .schedule
+            .as_mut()
+            .ok_or(StickyHabitsError::NotPeriodicHabit)?;
+        let period = (current_time - created_at) / schedule.cadence.period_length();
+        if period >= schedule.periods() {
+            return Err(StickyHabitsError::CheckInPeriodOver);
+        }
+
+        // Approved check-in cannot be replaced by evidence the beneficiaries have not seen
+        let check_in = &mut schedule.check_ins[period as usize];
+        if !check_in.approvals.is_empty() {
+            return Err(StickyHabitsError::CheckInAlreadyApproved(period as u16));
+        }
+        check_in.evidence = evidence.clone();
+        habit.status = HabitStatus::EvidenceSubmitted;
+        self.record_evidence(&habit, evidence.clone(), None);
+        let _updated = existing_habits.replace(index, &habit);
+        self.track_storage(&user, initial_storage, true)?;
+
+        HabitEvent::CheckInSubmitted(vec![CheckInSubmittedData {
+            user,
+            habit_id: id,
+            period: period as u16,
+            evidence,
+        }])
+        .emit();
+
+        Ok(())
+    }
+
+    // Beneficiary approves check-in of the period, until the approval grace period is over
+    #[handle_result]
+    pub fn approve_check_in(&mut self, id: String, period: u16) -> Result<(), StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+        let current_time = env::block_timestamp();
+
+        let (user, index) = self.habit_location(&id)?;
+        let (mut existing_habits, mut habit) = self.habit_at(&user, index)?;
+        if !habit.is_beneficiary(&account) {
+            return Err(StickyHabitsError::NotBeneficiary);
+        }
+        if !habit.beneficiary_accepted {
+            return Err(StickyHabitsError::BeneficiaryNotAccepted);
+        }
+        if habit.status.is_settled() {
+            return Err(StickyHabitsError::AlreadySettled);
+        }
+        if habit.status != HabitStatus::EvidenceSubmitted {
+            return Err(StickyHabitsError::InvalidStatus(habit.status));
+        }
+        if self.unlockable_at(&habit) <= current_time {
+            return Err(StickyHabitsError::OutsideApprovalWindow);
+        }
+        let check_in = habit
+            .schedule
+            .as_mut()
+            .ok_or(StickyHabitsError::NotPeriodicHabit)?
+            .check_ins
+            .get_mut(usize::from(period))
+            .ok_or(StickyHabitsError::IndexOutOfRange(u64::from(period)))?;
+        if check_in.evidence.is_empty() {
+            return Err(StickyHabitsError::MissingCheckIn(period));
+        }
+        if check_in.approvals.contains(&account) {
+            return Err(StickyHabitsError::AlreadyVoted);
+        }
+
+        check_in.approvals.push(account.clone());
+        let initial_storage = self.accounted_storage_usage();
+        let _updated = existing_habits.replace(index, &habit);
+        self.track_storage(&user, initial_storage, false)?;
+
+        HabitEvent::CheckInApproved(vec![CheckInApprovedData {
+            user,
+            habit_id: id,
+            period,
+            beneficiary: account,
+        }])
+        .emit();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{accept_role, register, set_context, DAY, NEAR, START};
+    use near_sdk::json_types::U128;
+    use std::str::FromStr;
+
+    // Creates contract with roman's daily habit for 4 days accepted by josef
+    fn setup() -> (StickyHabitsContract, String) {
+        let mut contract = StickyHabitsContract::default();
+
+        register(&mut contract, "roman");
+        set_context("roman", 40 * NEAR, START);
+        contract
+            .add_periodic_habit(
+                "Do 15 push-ups everyday".to_string(),
+                vec![AccountId::from_str("josef").unwrap()],
+                1,
+                Cadence::Daily,
+                4,
+                None,
+            )
+            .unwrap();
+        let id = contract
+            .get_habits_user(AccountId::from_str("roman").unwrap(), None, None)
+            .items[0]
+            .id
+            .clone();
+        accept_role(&mut contract, "roman", 0, START);
+
+        (contract, id)
+    }
+
+    #[test]
+    fn refunds_approved_periods() {
+        let (mut contract, id) = setup();
+        assert_eq!(
+            contract.get_habit(id.clone()).unwrap().deadline,
+            U64(START + 4 * DAY)
+        );
+
+        // Evidence for days 0, 1 and 3
+        for day in [0, 1, 3] {
+            set_context("roman", 0, START + day * DAY + 3600);
+            contract
+                .submit_check_in(id.clone(), format!("https://www.icloud.com/day{}.mov", day))
+                .unwrap();
+        }
+
+        // Approved check-in cannot be replaced within its period
+        set_context("josef", 0, START + 3 * DAY + 7200);
+        contract.approve_check_in(id.clone(), 3).unwrap();
+        set_context("roman", 0, START + 3 * DAY + 7200);
+        assert_eq!(
+            contract.submit_check_in(id.clone(), "https://www.icloud.com/day3b.mov".to_string()),
+            Err(StickyHabitsError::CheckInAlreadyApproved(3))
+        );
+
+        set_context("josef", 0, START + 4 * DAY);
+        assert_eq!(
+            contract.approve_check_in(id.clone(), 2),
+            Err(StickyHabitsError::MissingCheckIn(2))
+        );
+        contract.approve_check_in(id.clone(), 0).unwrap();
+        assert_eq!(
+            contract.approve_check_in(id.clone(), 3),
+            Err(StickyHabitsError::AlreadyVoted)
+        );
+        // Single rejection cannot forfeit the approved periods
+        assert_eq!(
+            contract.reject_habit_by_id(id.clone(), "Skipped day 2".to_string()),
+            Err(StickyHabitsError::PeriodicHabit)
+        );
+
+        // Half of the periods approved, half of the deposit is returned
+        let settlement = contract
+            .preview_settlement(AccountId::from_str("roman").unwrap(), id.clone())
+            .unwrap();
+        let deposit = 40 * NEAR;
+        assert_eq!(settlement.to_user, U128(deposit / 2));
+        assert_eq!(
+            settlement.to_developer,
+            U128(crate::settlement::split_deposit(deposit / 2, 500).1)
+        );
+
+        set_context("roman", 0, START + 20 * DAY);
+        contract.unlock_deposit_by_id(id.clone()).unwrap();
+        assert_eq!(
+            contract.get_habit(id).unwrap().status,
+            HabitStatus::PartiallyRefunded
+        );
+    }
+
+    #[test]
+    fn rejects_late_check_in() {
+        let (mut contract, id) = setup();
+
+        set_context("roman", 0, START);
+        assert_eq!(
+            contract.update_evidence_by_id(
+                id.clone(),
+                "https://www.icloud.com/day0.mov".to_string(),
+                None
+            ),
+            Err(StickyHabitsError::PeriodicHabit)
+        );
+        set_context("roman", 0, START + 4 * DAY);
+        assert_eq!(
+            contract.submit_check_in(id, "https://www.icloud.com/day4.mov".to_string()),
+            Err(StickyHabitsError::CheckInPeriodOver)
+        );
+        assert_eq!(
+            contract.add_periodic_habit(
+                "Do 15 push-ups everyday".to_string(),
+                vec![AccountId::from_str("josef").unwrap()],
+                1,
+                Cadence::Weekly,
+                0,
+                None
+            ),
+            Err(StickyHabitsError::InvalidPeriods(0))
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::Balance;
+
+// Generated code of construct_uint! does not pass clippy lints
+#[allow(clippy::all)]
+mod uint_types {
+    uint::construct_uint! {
+        // 256 bit unsigned integer, wide enough for deposit * basis points
+        pub struct U256(4);
+    }
+}
+
+use uint_types::U256;
+
+// 100% expressed in basis points
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+// Who gets what when a habit deposit is unlocked
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Settlement {
+    pub to_user: U128,
+    pub to_beneficiary: U128,
+    pub to_developer: U128,
+    // Deposit can be unlocked after this timestamp in nanoseconds
+    pub unlockable_at: U64,
+}
+
+impl Settlement {
+    // Refund goes back to the user, the rest of the deposit is split between
+    // beneficiary and developer
+    pub fn new(deposit: Balance, to_user: Balance, dev_fee_bps: u64, unlockable_at: u64) -> Self {
+        let (to_beneficiary, to_developer) = split_deposit(deposit - to_user, dev_fee_bps);
+
+        Self {
+            to_user: U128(to_user),
+            to_beneficiary: U128(to_beneficiary),
+            to_developer: U128(to_developer),
+            unlockable_at: U64(unlockable_at),
+        }
+    }
+}
+
+// Splits deposit into (remainder, fee), e.g. forfeited deposit into (to_beneficiary, to_developer).
+// Fee is rounded down, the remainder of the division stays with the first part,
+// so both parts always add up to the whole deposit and no yoctoNEAR is lost.
+pub fn split_deposit(deposit: Balance, fee_bps: u64) -> (Balance, Balance) {
+    assert!(
+        fee_bps <= BPS_DENOMINATOR,
+        "Fee cannot exceed {} basis points",
+        BPS_DENOMINATOR
+    );
+
+    let fee = (U256::from(deposit) * U256::from(fee_bps) / U256::from(BPS_DENOMINATOR)).as_u128();
+
+    (deposit - fee, fee)
+}
+
+// Returns numerator/denominator part of the amount, rounded down
+pub fn pro_rata(amount: Balance, numerator: u64, denominator: u64) -> Balance {
+    (U256::from(amount) * U256::from(numerator) / U256::from(denominator)).as_u128()
+}
+
+// Splits amount into equal shares, the remainder of the division goes to the first share
+pub fn split_evenly(amount: Balance, shares: usize) -> Vec<Balance> {
+    if shares == 0 {
+        return vec![];
+    }
+    let share = amount / shares as Balance;
+    let mut result = vec![share; shares];
+    result[0] += amount - share * shares as Balance;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::NEAR;
+
+    #[test]
+    fn splits_by_basis_points() {
+        assert_eq!(split_deposit(10 * NEAR, 500), (95 * NEAR / 10, NEAR / 2));
+        assert_eq!(split_deposit(10 * NEAR, 0), (10 * NEAR, 0));
+        assert_eq!(split_deposit(10 * NEAR, 10_000), (0, 10 * NEAR));
+    }
+
+    #[test]
+    fn splits_evenly() {
+        assert_eq!(split_evenly(9 * NEAR, 3), vec![3 * NEAR; 3]);
+        assert_eq!(split_evenly(10, 3), vec![4, 3, 3]);
+        assert_eq!(split_evenly(10, 1), vec![10]);
+    }
+
+    #[test]
+    fn rounds_developer_fee_down() {
+        // 5% of 99 yoctoNEAR is 4.95, developer gets 4 and beneficiary the rest
+        assert_eq!(split_deposit(99, 500), (95, 4));
+
+        for deposit in [1, 7, 19_999, u128::MAX] {
+            let (to_beneficiary, to_developer) = split_deposit(deposit, 333);
+            assert_eq!(to_beneficiary + to_developer, deposit);
+        }
+    }
+
+    #[test]
+    fn previews_approved_habit() {
+        let settlement = Settlement::new(10 * NEAR, 10 * NEAR, 500, 42);
+        assert_eq!(settlement.to_user, U128(10 * NEAR));
+        assert_eq!(settlement.to_beneficiary, U128(0));
+        assert_eq!(settlement.to_developer, U128(0));
+    }
+
+    #[test]
+    fn previews_partial_refund() {
+        // 2 of 3 periods approved
+        let to_user = pro_rata(9 * NEAR, 2, 3);
+        let settlement = Settlement::new(9 * NEAR, to_user, 1000, 42);
+        assert_eq!(settlement.to_user, U128(6 * NEAR));
+        assert_eq!(settlement.to_beneficiary, U128(27 * NEAR / 10));
+        assert_eq!(settlement.to_developer, U128(3 * NEAR / 10));
+        assert_eq!(pro_rata(10, 1, 3), 3);
+    }
+}
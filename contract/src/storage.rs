@@ -0,0 +1,382 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance, Promise, StorageUsage};
+
+use crate::{StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+// Bytes covered by the minimal storage deposit, enough for the registration and a habit
+pub const MIN_STORAGE_BYTES: StorageUsage = 1200;
+
+// Storage staked by the account and bytes used by its habits
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StorageAccount {
+    pub total: Balance,
+    pub used: StorageUsage,
+}
+
+// NEP-145 storage balance of an account
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+// NEP-145 bounds of storage deposit, there is no upper bound
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
+}
+
+impl StorageAccount {
+    fn available(&self) -> Balance {
+        self.total
+            .saturating_sub(Balance::from(self.used) * env::storage_byte_cost())
+    }
+
+    fn balance(&self) -> StorageBalance {
+        StorageBalance {
+            total: U128(self.total),
+            available: U128(self.available()),
+        }
+    }
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // Stakes attached deposit for storage of habits of the account, the caller by default.
+    // Registration only deposit keeps just the minimum and refunds the rest.
+    #[payable]
+    #[handle_result]
+    pub fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> Result<StorageBalance, StickyHabitsError> {
+        let amount: Balance = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let registration_only = registration_only.unwrap_or(false);
+
+        let refund = match self.storage_accounts.get(&account_id) {
+            Some(_) if registration_only => amount,
+            Some(mut storage) => {
+                storage.total += amount;
+                self.storage_deposits += amount;
+                self.storage_accounts.insert(&account_id, &storage);
+                0
+            }
+            None => {
+                let min = u128::from(self.storage_balance_bounds().min);
+                if amount < min {
+                    return Err(StickyHabitsError::InsufficientDeposit(min));
+                }
+                let total = if registration_only { min } else { amount };
+
+                // Registration itself is paid from the deposit
+                let initial_storage = env::storage_usage();
+                let mut storage = StorageAccount { total, used: 0 };
+                self.storage_accounts.insert(&account_id, &storage);
+                storage.used = env::storage_usage() - initial_storage;
+                self.storage_accounts.insert(&account_id, &storage);
+                self.storage_deposits += total;
+                amount - total
+            }
+        };
+        if refund > 0 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+
+        Ok(self.storage_accounts.get(&account_id).unwrap().balance())
+    }
+
+    // Withdraws storage deposit not used by habits, all available by default.
+    // Requires exactly 1 yoctoNEAR attached.
+    #[payable]
+    #[handle_result]
+    pub fn storage_withdraw(
+        &mut self,
+        amount: Option<U128>,
+    ) -> Result<StorageBalance, StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+
+        if env::attached_deposit() != 1 {
+            return Err(StickyHabitsError::OneYoctoRequired);
+        }
+        let mut storage = self
+            .storage_accounts
+            .get(&account)
+            .ok_or_else(|| StickyHabitsError::StorageNotRegistered(account.clone()))?;
+        let available = storage.available();
+        let amount = amount.map_or(available, u128::from);
+        if amount > available {
+            return Err(StickyHabitsError::InsufficientStorageBalance(
+                amount - available,
+            ));
+        }
+
+        storage.total -= amount;
+        self.storage_accounts.insert(&account, &storage);
+        self.storage_deposits -= amount;
+        if amount > 0 {
+            Promise::new(account).transfer(amount);
+        }
+
+        Ok(storage.balance())
+    }
+
+    // Removes registration of the caller and refunds its whole storage deposit. Refused while
+    // the account uses storage for anything but the registration, also when forced, as data
+    // of the account is never dropped. Returns false if the caller is not registered.
+    // Requires exactly 1 yoctoNEAR attached.
+    #[allow(unused_variables)]
+    #[payable]
+    #[handle_result]
+    pub fn storage_unregister(&mut self, force: Option<bool>) -> Result<bool, StickyHabitsError> {
+        let account: AccountId = env::predecessor_account_id();
+
+        if env::attached_deposit() != 1 {
+            return Err(StickyHabitsError::OneYoctoRequired);
+        }
+        let storage = match self.storage_accounts.get(&account) {
+            Some(storage) => storage,
+            None => return Ok(false),
+        };
+
+        // Active habits always use storage, only the registration itself may be left
+        let initial_storage = env::storage_usage();
+        self.storage_accounts.remove(&account);
+        if storage.used > initial_storage - env::storage_usage() {
+            self.storage_accounts.insert(&account, &storage);
+            return Err(StickyHabitsError::StorageInUse);
+        }
+        self.storage_deposits -= storage.total;
+        Promise::new(account).transfer(storage.total);
+
+        Ok(true)
+    }
+
+    // Returns storage balance of the account, None if not registered
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts.get(&account_id).map(|s| s.balance())
+    }
+
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(Balance::from(MIN_STORAGE_BYTES) * env::storage_byte_cost()),
+            max: None,
+        }
+    }
+}
+
+impl StickyHabitsContract {
+    // Contract storage without the claimable ledger, ledger entries are charged to their
+    // payers by charge_storage and release_storage
+    pub(crate) fn accounted_storage_usage(&self) -> StorageUsage {
+        env::storage_usage() - self.claims_storage
+    }
+
+    // Adds bytes to the storage used by the account, it may go over its storage balance, so
+    // that settlements are never blocked
+    pub(crate) fn charge_storage(&mut self, account: &AccountId, bytes: StorageUsage) {
+        if let Some(mut storage) = self.storage_accounts.get(account) {
+            storage.used += bytes;
+            self.storage_accounts.insert(account, &storage);
+        }
+    }
+
+    pub(crate) fn release_storage(&mut self, account: &AccountId, bytes: StorageUsage) {
+        if let Some(mut storage) = self.storage_accounts.get(account) {
+            storage.used = storage.used.saturating_sub(bytes);
+            self.storage_accounts.insert(account, &storage);
+        }
+    }
+
+    // Accounts storage grown or released since initial_storage to the account. When required,
+    // growth has to be covered by the account's storage balance, otherwise the account may
+    // go over it, so that beneficiaries and arbiters are never blocked by the user.
+    pub(crate) fn track_storage(
+        &mut self,
+        account: &AccountId,
+        initial_storage: StorageUsage,
+        required: bool,
+    ) -> Result<(), StickyHabitsError> {
+        let current_storage = self.accounted_storage_usage();
+
+        let mut storage = match self.storage_accounts.get(account) {
+            Some(storage) => storage,
+            None if required && current_storage > initial_storage => {
+                return Err(StickyHabitsError::StorageNotRegistered(account.clone()))
+            }
+            None => return Ok(()),
+        };
+        storage.used = (storage.used + current_storage).saturating_sub(initial_storage);
+        let cost = Balance::from(storage.used) * env::storage_byte_cost();
+        if required && current_storage > initial_storage && cost > storage.total {
+            return Err(StickyHabitsError::InsufficientStorageBalance(
+                cost - storage.total,
+            ));
+        }
+        self.storage_accounts.insert(account, &storage);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{add_habit, register, set_context, NEAR, START};
+    use near_sdk::json_types::U64;
+    use std::str::FromStr;
+
+    #[test]
+    fn manages_storage() {
+        let mut contract = StickyHabitsContract::default();
+        let roman = AccountId::from_str("roman").unwrap();
+        let min = u128::from(contract.storage_balance_bounds().min);
+
+        set_context("roman", 20 * NEAR, START);
+        assert_eq!(
+            contract.add_habit(
+                "Do 15 push-ups everyday".to_string(),
+                U64(0),
+                AccountId::from_str("josef").unwrap(),
+                None
+            ),
+            Err(StickyHabitsError::StorageNotRegistered(roman.clone()))
+        );
+        set_context("roman", min - 1, START);
+        assert_eq!(
+            contract.storage_deposit(None, None),
+            Err(StickyHabitsError::InsufficientDeposit(min))
+        );
+
+        // Registration keeps the minimum and is paid from it
+        set_context("roman", NEAR, START);
+        let balance = contract.storage_deposit(None, Some(true)).unwrap();
+        assert_eq!(balance.total, U128(min));
+        assert!(u128::from(balance.available) < min);
+
+        add_habit(
+            &mut contract,
+            "roman",
+            "Do 15 push-ups everyday",
+            "josef",
+            20 * NEAR,
+        );
+        // Whole deposit is locked, storage is paid from storage balance
+        assert_eq!(
+            contract.get_habits_user(roman.clone(), None, None).items[0].deposit,
+            U128(20 * NEAR)
+        );
+        assert!(
+            u128::from(
+                contract
+                    .storage_balance_of(roman.clone())
+                    .unwrap()
+                    .available
+            ) < u128::from(balance.available)
+        );
+
+        // Claimable entries are charged to the user of the habit
+        let used = |contract: &StickyHabitsContract| {
+            contract
+                .storage_accounts
+                .get(&AccountId::from_str("roman").unwrap())
+                .unwrap()
+                .used
+        };
+        set_context("roman", NEAR, START);
+        contract.storage_deposit(None, None).unwrap();
+        set_context("josef", 0, START);
+        let id = contract.get_habits_user(roman.clone(), None, None).items[0]
+            .id
+            .clone();
+        let (initial_storage, initial_used) = (env::storage_usage(), used(&contract));
+        contract.decline_beneficiary_role(id).unwrap();
+        assert!(contract.claims_storage > 0);
+        assert_eq!(
+            used(&contract) - initial_used,
+            env::storage_usage() - initial_storage
+        );
+
+        // Withdrawal releases the entry back to the user's storage
+        let (used_before, claims_storage) = (used(&contract), contract.claims_storage);
+        set_context("roman", 1, START);
+        contract.withdraw(None, None, None).unwrap();
+        assert_eq!(contract.claims_storage, 0);
+        assert_eq!(used(&contract), used_before - claims_storage);
+
+        set_context("roman", 0, START);
+        assert_eq!(
+            contract.storage_withdraw(None),
+            Err(StickyHabitsError::OneYoctoRequired)
+        );
+        set_context("roman", 1, START);
+        assert_eq!(
+            contract.storage_withdraw(Some(U128(min + NEAR))),
+            Err(StickyHabitsError::InsufficientStorageBalance(
+                min + NEAR
+                    - u128::from(
+                        contract
+                            .storage_balance_of(roman.clone())
+                            .unwrap()
+                            .available
+                    )
+            ))
+        );
+        let balance = contract.storage_withdraw(None).unwrap();
+        assert_eq!(balance.available, U128(0));
+        assert!(contract
+            .storage_balance_of("josef".parse().unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn unregisters_storage() {
+        let mut contract = StickyHabitsContract::default();
+
+        set_context("adam", 1, START);
+        assert_eq!(contract.storage_unregister(None), Ok(false));
+        register(&mut contract, "adam");
+        set_context("adam", 0, START);
+        assert_eq!(
+            contract.storage_unregister(None),
+            Err(StickyHabitsError::OneYoctoRequired)
+        );
+
+        // Account with a habit keeps its registration
+        register(&mut contract, "roman");
+        add_habit(
+            &mut contract,
+            "roman",
+            "Do 15 push-ups everyday",
+            "josef",
+            20 * NEAR,
+        );
+        set_context("roman", 1, START);
+        assert_eq!(
+            contract.storage_unregister(Some(true)),
+            Err(StickyHabitsError::StorageInUse)
+        );
+        assert!(contract
+            .storage_balance_of("roman".parse().unwrap())
+            .is_some());
+
+        // Account with registration only gets its whole deposit back
+        set_context("adam", 1, START);
+        assert_eq!(contract.storage_unregister(None), Ok(true));
+        assert!(contract
+            .storage_balance_of("adam".parse().unwrap())
+            .is_none());
+        assert_eq!(contract.storage_deposits, NEAR);
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        assert_eq!(
+            receipts[0].receiver_id,
+            "adam".parse::<AccountId>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json;
+use near_sdk::{env, ext_contract, near_bindgen, AccountId, Balance, Gas, Promise};
+
+use crate::{Stake, StickyHabitsContract, StickyHabitsContractExt, StickyHabitsError};
+
+const FT_TRANSFER_GAS: Gas = Gas(10_000_000_000_000);
+
+// NEP-141 token contract
+#[ext_contract(ext_ft)]
+#[allow(dead_code)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+// Message of ft_transfer_call creating a habit staked with the transferred tokens
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AddHabitMsg {
+    pub description: String,
+    pub deadline_extension: Option<U64>,
+    pub beneficiaries: Vec<AccountId>,
+    pub approval_threshold: Option<u16>,
+    pub private: Option<bool>,
+}
+
+#[near_bindgen]
+impl StickyHabitsContract {
+    // NEP-141 receiver, adds habit of the sender staked with tokens of the calling whitelisted
+    // token contract. Failed call returns the tokens to the sender.
+    #[handle_result]
+    pub fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> Result<U128, StickyHabitsError> {
+        let token: AccountId = env::predecessor_account_id();
+
+        if !self.whitelisted_tokens.contains(&token) {
+            return Err(StickyHabitsError::TokenNotWhitelisted(token));
+        }
+        let msg: AddHabitMsg = serde_json::from_str(&msg)
+            .map_err(|e| StickyHabitsError::InvalidMessage(e.to_string()))?;
+
+        self.internal_add_habit(
+            Stake {
+                user: sender_id,
+                amount: u128::from(amount),
+                token: Some(token),
+            },
+            msg.description,
+            msg.deadline_extension.unwrap_or(U64(0)),
+            msg.beneficiaries,
+            msg.approval_threshold.unwrap_or(1),
+            None,
+            msg.private.unwrap_or(false),
+        )?;
+
+        // All tokens are used
+        Ok(U128(0))
+    }
+
+    // Returns tokens accepted as stakes
+    pub fn get_whitelisted_tokens(&self) -> Vec<AccountId> {
+        self.whitelisted_tokens.to_vec()
+    }
+
+    // Returns amount of the token locked in habits
+    pub fn get_token_balance(&self, token: AccountId) -> U128 {
+        U128(self.token_balances.get(&token).unwrap_or(0))
+    }
+}
+
+impl StickyHabitsContract {
+    // Adds the amount to the total locked in NEAR or in the token
+    pub(crate) fn lock_stake(&mut self, token: &Option<AccountId>, amount: Balance) {
+        match token {
+            Some(token) => {
+                let locked = self.token_balances.get(token).unwrap_or(0);
+                self.token_balances.insert(token, &(locked + amount));
+            }
+            None => self.balance += amount,
+        }
+    }
+
+    pub(crate) fn unlock_stake(&mut self, token: &Option<AccountId>, amount: Balance) {
+        match token {
+            Some(token) => {
+                let locked = self.token_balances.get(token).unwrap_or(0);
+                self.token_balances.insert(token, &(locked - amount));
+            }
+            None => self.balance -= amount,
+        }
+    }
+
+    // Pays out part of a stake in NEAR or by ft_transfer, which requires 1 yoctoNEAR
+    // paid by the contract
+    pub(crate) fn transfer_stake(
+        &self,
+        token: &Option<AccountId>,
+        receiver: AccountId,
+        amount: Balance,
+    ) -> Promise {
+        match token {
+            Some(token) => ext_ft::ext(token.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(FT_TRANSFER_GAS)
+                .ft_transfer(receiver, U128(amount), None),
+            None => Promise::new(receiver).transfer(amount),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{register, set_context, DAY, OWNER, START};
+    use crate::HabitStatus;
+    use near_sdk::test_utils::get_created_receipts;
+    use std::str::FromStr;
+
+    const USDC: u128 = 1000000;
+
+    const MSG: &str = r#"{"description":"Do 15 push-ups everyday","beneficiaries":["josef"]}"#;
+
+    #[test]
+    fn stakes_tokens() {
+        set_context(OWNER, 0, START);
+        let mut contract =
+            StickyHabitsContract::init(OWNER.parse().unwrap(), U64(500), U64(DAY), U64(DAY));
+        let roman = AccountId::from_str("roman").unwrap();
+        let usdc = AccountId::from_str("usdc.near").unwrap();
+
+        register(&mut contract, "roman");
+
+        set_context("usdc.near", 0, START);
+        assert_eq!(
+            contract.ft_on_transfer(roman.clone(), U128(100 * USDC), MSG.to_string()),
+            Err(StickyHabitsError::TokenNotWhitelisted(usdc.clone()))
+        );
+        set_context(OWNER, 1, START);
+        contract.add_whitelisted_token(usdc.clone()).unwrap();
+        assert_eq!(contract.get_whitelisted_tokens(), vec![usdc.clone()]);
+
+        set_context("usdc.near", 0, START);
+        assert!(matches!(
+            contract.ft_on_transfer(roman.clone(), U128(100 * USDC), "{}".to_string()),
+            Err(StickyHabitsError::InvalidMessage(_))
+        ));
+        assert_eq!(
+            contract.ft_on_transfer(roman.clone(), U128(100 * USDC), MSG.to_string()),
+            Ok(U128(0))
+        );
+        let habit = &contract.get_habits_user(roman.clone(), None, None).items[0];
+        assert_eq!(habit.token, Some(usdc.clone()));
+        assert_eq!(habit.deposit, U128(100 * USDC));
+        assert_eq!(contract.get_token_balance(usdc.clone()), U128(100 * USDC));
+        assert_eq!(contract.balance, 0);
+
+        // Not accepted habit is refunded in tokens
+        set_context("roman", 0, START + 4 * DAY);
+        contract.unlock_deposit_by_id(habit.id.clone()).unwrap();
+        assert_eq!(
+            contract.get_habit(habit.id.clone()).unwrap().status,
+            HabitStatus::Refunded
+        );
+        assert_eq!(contract.get_token_balance(usdc.clone()), U128(0));
+        assert_eq!(
+            contract.get_claimable(roman.clone(), Some(usdc.clone())),
+            U128(100 * USDC)
+        );
+        assert_eq!(contract.get_claimable(roman, None), U128(0));
+
+        set_context("roman", 1, START + 4 * DAY);
+        contract.withdraw(None, Some(usdc), None).unwrap();
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].receiver_id.as_str(), "usdc.near");
+        assert!(format!("{:?}", receipts[0].actions).contains("ft_transfer"));
+        assert!(format!("{:?}", receipts[1].actions).contains("on_withdraw"));
+    }
+}
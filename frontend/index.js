@@ -18,6 +18,7 @@ window.onload = async () => {
   if (isSignedIn) {
     signedInFlow();
     await fetchHabits();
+    await fetchClaimable();
 
   } else {
     signedOutFlow();
@@ -29,6 +30,7 @@ window.onload = async () => {
 document.querySelector('form').onsubmit = doUserAction;
 document.querySelector('#sign-in-button').onclick = () => { wallet.signIn(); };
 document.querySelector('#sign-out-button').onclick = () => { wallet.signOut(); };
+document.querySelector('#withdraw-button').onclick = doWithdraw;
 
 // Take the new habit and send it to the contract
 async function doUserAction(event) {
@@ -36,6 +38,9 @@ async function doUserAction(event) {
   const { description, deadline_extension, deposit, beneficiary } = event.target.elements;
 
   try {
+    if (await stickyHabits.storageBalanceOf() === null) {
+      await stickyHabits.storageDeposit("0.1");
+    }
     await stickyHabits.addHabit(description.value, deadline_extension.value, deposit.value, beneficiary.value);
   } catch (e) {
     alert(
@@ -48,6 +53,28 @@ async function doUserAction(event) {
 
 }
 
+// Settled deposits are credited to the account and paid out only on withdrawal
+async function doWithdraw() {
+  try {
+    await stickyHabits.withdraw();
+  } catch (e) {
+    alert(
+        'Something went wrong! ' +
+        'Maybe you need to sign out and back in? ' +
+        'Check your browser console for more info.'
+    )
+    throw e
+  }
+}
+
+// Get amount the account can withdraw from the contract on chain
+async function fetchClaimable() {
+  const claimable = await stickyHabits.getClaimable(wallet.accountId);
+
+  document.getElementById('claimable').innerText = utils.format.formatNearAmount(claimable);
+  document.querySelector('#withdraw-button').disabled = claimable === '0';
+}
+
 // Get habits from the contract on chain
 async function fetchHabits() {
   const userHabits = await stickyHabits.getUserHabits();
@@ -57,7 +84,7 @@ async function fetchHabits() {
 
   document.getElementById('user-habits-table').innerHTML = ''
 
-  userHabits.forEach(elem => {
+  userHabits.items.forEach(elem => {
     const depositinNear = utils.format.formatNearAmount(elem.deposit)
     const date = new Date(elem.deadline / 1000000);
     let tr = document.createElement('tr')
@@ -67,34 +94,32 @@ async function fetchHabits() {
         <td>${elem.description}</td>
         <td>${date}</td>
         <td>${depositinNear}</td>
-        <td>${elem.beneficiary}</td>
+        <td>${elem.beneficiaries.join(", ")}</td>
         <td>${elem.evidence}</td>
-        <td>${elem.approved}</td>
+        <td>${elem.status}</td>
       </tr>
     `
     document.getElementById('user-habits-table').appendChild(tr)
   });
 
   document.getElementById('beneficiary-habits-table').innerHTML = ''
-  for (const user in beneficiaryHabits) {
-    beneficiaryHabits[user].forEach(elem => {
-      const depositinNear = utils.format.formatNearAmount(elem.deposit)
-      const date = new Date(elem.deadline / 1000000);
-      let tr = document.createElement('tr')
-      tr.innerHTML = `
+  beneficiaryHabits.items.forEach(({ habit: elem }) => {
+    const depositinNear = utils.format.formatNearAmount(elem.deposit)
+    const date = new Date(elem.deadline / 1000000);
+    let tr = document.createElement('tr')
+    tr.innerHTML = `
       <tr>
         <th scope="row">${elem.id}</th>
         <td>${elem.description}</td>
         <td>${date}</td>
         <td>${depositinNear}</td>
-        <td>${elem.beneficiary}</td>
+        <td>${elem.beneficiaries.join(", ")}</td>
         <td>${elem.evidence}</td>
-        <td>${elem.approved}</td>
+        <td>${elem.status}</td>
       </tr>
     `
-      document.getElementById('beneficiary-habits-table').appendChild(tr)
-    });
-  }
+    document.getElementById('beneficiary-habits-table').appendChild(tr)
+  });
 }
 
 // Display the signed-out-flow container
@@ -12,7 +12,7 @@ init () {
   echo ">> Initializing contract at $CONTRACT_ACCOUNT"
   local_near call ${CONTRACT_ACCOUNT} init '{
       "owner": "zajda.test.near",
-      "dev_fee": "5",
+      "dev_fee_bps": "500",
       "habit_acquisition_period": "60000000000",
       "approval_grace_period": "60000000000"
   }' --accountId ${CONTRACT_ACCOUNT}
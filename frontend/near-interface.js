@@ -9,22 +9,34 @@ export class StickyHabits {
 
     }
 
-    async getUserHabits() {
-        const min = 0;
+    // Returns page of habits, pass its next_cursor as cursor to get the following one
+    async getUserHabits(cursor = null) {
         const limit = 7;
         return await this.wallet.viewMethod({ contractId: this.contractId, method: 'get_habits_user',
-             args:{ user: this.wallet.accountId, from_index: min.toString(), limit_to: limit.toString() }});
+             args:{ user: this.wallet.accountId, cursor: cursor, limit_to: limit }});
 
     }
 
-    async getBeneficiaryHabits() {
-        const min = 0;
+    async getBeneficiaryHabits(cursor = null) {
         const limit = 7;
         return await this.wallet.viewMethod({ contractId: this.contractId, method: 'get_habits_beneficiary',
-            args:{ beneficiary: this.wallet.accountId, from_index: min.toString(), limit_to: limit.toString() }});
+            args:{ beneficiary: this.wallet.accountId, cursor: cursor, limit_to: limit }});
 
     }
 
+    async storageBalanceOf() {
+        return await this.wallet.viewMethod({ contractId: this.contractId, method: 'storage_balance_of',
+            args:{ account_id: this.wallet.accountId }});
+    }
+
+    // Storage of habits is paid from the storage deposit, not from the habit's deposit
+    async storageDeposit(deposit) {
+        const depositInYocto = utils.format.parseNearAmount(deposit);
+
+        return await this.wallet.callMethod({ contractId: this.contractId, method: 'storage_deposit',
+            args:{}, deposit: depositInYocto });
+    }
+
     async addHabit(description, deadline_extension, deposit, beneficiary) {
         const depositInYocto = utils.format.parseNearAmount(deposit);
         const THIRTY_TGAS = '30000000000000';
@@ -50,5 +62,16 @@ export class StickyHabits {
             args:{ user: user, at_index: at_index.toString() } });
     }
 
+    async getClaimable(account) {
+        return await this.wallet.viewMethod({ contractId: this.contractId, method: 'get_claimable',
+            args:{ account: account } });
+    }
+
+    // Withdrawal requires exactly 1 yoctoNEAR and is checked by a callback
+    async withdraw() {
+        return await this.wallet.callMethod({ contractId: this.contractId, method: 'withdraw',
+            args:{}, gas: '100000000000000', deposit: '1' });
+    }
+
 
 }
\ No newline at end of file
@@ -8,14 +8,25 @@ export type User = {
   id: number
   name: string
 }
+export type HabitStatus =
+  | 'Pending'
+  | 'EvidenceSubmitted'
+  | 'Approved'
+  | 'Rejected'
+  | 'Refunded'
+  | 'Forfeited'
+  | 'Cancelled'
+  | 'Disputed'
+  | 'PartiallyRefunded';
+
 // TODO: extend Habit datatype with an ID for each habit
 export type Habit = {
   id: string,
   description: string,
   deadline: string,
   deposit: number,
-  beneficiary: string,
+  beneficiaries: string[],
   evidence: string,
-  approved: boolean,
+  status: HabitStatus,
 
 };
\ No newline at end of file
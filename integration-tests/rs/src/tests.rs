@@ -6,6 +6,8 @@ use serde_json::json;
 use workspaces::{network::Sandbox, Account, Contract, Worker};
 
 const WASM_FILEPATH: &str = "../../out/main.wasm";
+// Contract built from the last release without versioned state
+const LEGACY_WASM_FILEPATH: &str = "../../out/legacy.wasm";
 
 // Create custom Sticky Habits contract and setup the initial state.
 async fn create_sticky_habits(
@@ -19,7 +21,7 @@ async fn create_sticky_habits(
     sh.call("init")
         .args_json(json!({
             "owner": owner.id(),
-            "dev_fee": U64(5),
+            "dev_fee_bps": U64(500),
             "habit_acquisition_period": U64(10*1000000000), // 10 sec
             "approval_grace_period": U64(10*1000000000)     // 10 sec
         }))
@@ -36,6 +38,17 @@ async fn test_default_workflow(
     contract: &Contract,
 ) -> anyhow::Result<()> {
 
+    // Deposit storage for user's habits
+    let sd_res = user
+        .call(contract.id(), "storage_deposit")
+        .args_json(json!({}))
+        .deposit(parse_near!("0.1 N"))
+        .transact()
+        .await?
+        .into_result()?;
+
+    println!("Storage deposit response: {:?}\n", sd_res);
+
     // Add habit
     let ah_res = user
         .call(contract.id(), "add_habit")
@@ -51,6 +64,26 @@ async fn test_default_workflow(
 
     println!("Add habit response: {:?}\n", ah_res);
 
+    let habits: serde_json::Value = contract
+        .view(
+            "get_habits_user",
+            json!({ "user": user.id() }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+
+    // Beneficiary accepts the role
+    let ar_res = beneficiary
+        .call(contract.id(), "accept_beneficiary_role")
+        .args_json(json!({
+            "id": habits["items"][0]["id"],
+            }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    println!("Accept beneficiary role response: {:?}\n", ar_res);
+
     // Update evidence
     let ue_res = user
         .call(contract.id(), "update_evidence")
@@ -97,10 +130,210 @@ async fn test_default_workflow(
 
     println!("Unlock deposit response: {:?}\n", ud_res);
 
+    // Withdraw unlocked deposit
+    let wd_res = user
+        .call(contract.id(), "withdraw")
+        .args_json(json!({}))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    println!("Withdraw response: {:?}\n", wd_res);
+
     println!("Passed ✅ default workflow");
     Ok(())
 }
 
+async fn test_upgrade(
+    owner: &Account,
+    user: &Account,
+    beneficiary: &Account,
+    worker: &Worker<Sandbox>,
+) -> anyhow::Result<()> {
+    // Deploy and populate legacy contract
+    let legacy: Contract = worker
+        .dev_deploy(&std::fs::read(LEGACY_WASM_FILEPATH)?)
+        .await?;
+
+    legacy.call("init")
+        .args_json(json!({
+            "owner": owner.id(),
+            "dev_fee": U64(5),
+            "habit_acquisition_period": U64(10*1000000000), // 10 sec
+            "approval_grace_period": U64(10*1000000000)     // 10 sec
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    for description in ["Drink a glass of water after waking up", "Read 10 pages every evening"] {
+        user.call(legacy.id(), "add_habit")
+            .args_json(json!({
+                "description": description.to_string(),
+                "deadline_extension": U64(0),
+                "beneficiary": beneficiary.id()
+                }))
+            .deposit(parse_near!("5 N"))
+            .transact()
+            .await?
+            .into_result()?;
+    }
+
+    // Legacy contract has no upgrade method, its account deploys current code and migrates
+    legacy
+        .as_account()
+        .deploy(&std::fs::read(WASM_FILEPATH)?)
+        .await?
+        .into_result()?;
+    let mg_res = legacy
+        .call("migrate")
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    println!("Migrate response: {:?}\n", mg_res);
+
+    // Following upgrades go through the owner
+    let up_res = owner
+        .call(legacy.id(), "upgrade")
+        .args(std::fs::read(WASM_FILEPATH)?)
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    println!("Upgrade response: {:?}\n", up_res);
+
+    let habits: serde_json::Value = legacy
+        .view(
+            "get_habits_user",
+            json!({ "user": user.id(), "limit_to": 2 }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+
+    assert_eq!(habits["items"][1]["description"], "Read 10 pages every evening");
+    assert_ne!(habits["items"][0]["id"], habits["items"][1]["id"]);
+
+    // Migrated habit can be looked up by its new id
+    let habit: serde_json::Value = legacy
+        .view(
+            "get_habit",
+            json!({ "id": habits["items"][1]["id"] }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+
+    assert_eq!(habit["beneficiaries"][0], beneficiary.id().to_string());
+
+    println!("Passed ✅ upgrade");
+    Ok(())
+}
+
+async fn test_failed_withdrawal(
+    owner: &Account,
+    beneficiary: &Account,
+    contract: &Contract,
+) -> anyhow::Result<()> {
+    let carol = owner
+        .create_subaccount("carol")
+        .initial_balance(parse_near!("10 N"))
+        .transact()
+        .await?
+        .into_result()?;
+
+    carol.call(contract.id(), "storage_deposit")
+        .args_json(json!({}))
+        .deposit(parse_near!("0.1 N"))
+        .transact()
+        .await?
+        .into_result()?;
+
+    carol.call(contract.id(), "add_habit")
+        .args_json(json!({
+            "description": "Go to bed before midnight".to_string(),
+            "deadline_extension": U64(0),
+            "beneficiary": beneficiary.id()
+            }))
+        .deposit(parse_near!("5 N"))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let habits: serde_json::Value = contract
+        .view(
+            "get_habits_user",
+            json!({ "user": carol.id() }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+
+    // Declined habit is credited to carol
+    beneficiary
+        .call(contract.id(), "decline_beneficiary_role")
+        .args_json(json!({ "id": habits["items"][0]["id"] }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    // Withdrawal to an account deleted beforehand fails
+    let dave = owner
+        .create_subaccount("dave")
+        .initial_balance(parse_near!("1 N"))
+        .transact()
+        .await?
+        .into_result()?;
+    let dave_id = dave.id().clone();
+    dave.delete_account(owner.id()).await?.into_result()?;
+
+    let wd_res = carol
+        .call(contract.id(), "withdraw")
+        .args_json(json!({ "receiver": dave_id }))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    println!("Withdraw response: {:?}\n", wd_res);
+
+    // Failed transfer is credited back to carol
+    let claimable: serde_json::Value = contract
+        .view(
+            "get_claimable",
+            json!({ "account": carol.id() }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+
+    assert_eq!(claimable, parse_near!("5 N").to_string());
+
+    // Carol withdraws to herself
+    carol.call(contract.id(), "withdraw")
+        .args_json(json!({}))
+        .deposit(1)
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+
+    let claimable: serde_json::Value = contract
+        .view(
+            "get_claimable",
+            json!({ "account": carol.id() }).to_string().into_bytes(),
+        )
+        .await?
+        .json()?;
+
+    assert_eq!(claimable, "0");
+
+    println!("Passed ✅ failed withdrawal");
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let worker = workspaces::sandbox().await?;
@@ -127,6 +360,8 @@ async fn main() -> anyhow::Result<()> {
 
     // Begin tests
     test_default_workflow(&alice,&bob, &contract).await?;
+    test_failed_withdrawal(&owner, &bob, &contract).await?;
+    test_upgrade(&owner, &alice, &bob, &worker).await?;
 
 
     Ok(())